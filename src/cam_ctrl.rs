@@ -8,24 +8,41 @@ use bevy::{
 
 use crate::{
     AppState,
-    tilemap::{MAP_SIZE, TILE_SCALE},
+    tilemap::{MapSize, TILE_SCALE},
 };
 
-pub const CAMERA_START: Vec3 = Vec3::new(
-    TILE_SCALE * MAP_SIZE as f32 * 1.2,
-    TILE_SCALE * MAP_SIZE as f32 * 0.75,
-    TILE_SCALE * MAP_SIZE as f32 * 1.2,
-);
-pub const CAMERA_START_LOC: Transform =
-    Transform::from_xyz(CAMERA_START.x, CAMERA_START.y, CAMERA_START.z);
-
-pub const CAMERA_EDITOR: Vec3 = Vec3::new(
-    MAP_SIZE as f32 * TILE_SCALE / 2.,
-    MAP_SIZE as f32 * TILE_SCALE,
-    MAP_SIZE as f32 * TILE_SCALE / 2.,
-);
-pub const CAMERA_EDITOR_LOC: Transform =
-    Transform::from_xyz(CAMERA_EDITOR.x, CAMERA_EDITOR.y, CAMERA_EDITOR.z);
+/// Game view camera location, looking diagonally across the map
+pub fn camera_start(map_size: &MapSize) -> Vec3 {
+    let span = map_size.world_extent().max_element();
+    map_size.world_center() + Vec3::new(span * 0.7, span * 0.75, span * 0.7)
+}
+
+pub fn camera_start_loc(map_size: &MapSize) -> Transform {
+    Transform::from_translation(camera_start(map_size))
+        .looking_at(map_size.world_center(), Vec3::Y)
+}
+
+/// Editor view camera location, directly above the map centre
+pub fn camera_editor(map_size: &MapSize) -> Vec3 {
+    map_size.world_center() + Vec3::Y * map_size.world_extent().max_element()
+}
+
+pub fn camera_editor_loc(map_size: &MapSize) -> Transform {
+    Transform::from_translation(camera_editor(map_size))
+        .looking_at(map_size.world_center(), -Vec3::Z)
+}
+
+/// Orthographic projection which fits the whole map in view
+pub fn camera_projection(map_size: &MapSize) -> Projection {
+    let extent = map_size.world_extent();
+    Projection::Orthographic(OrthographicProjection {
+        scaling_mode: ScalingMode::AutoMin {
+            min_width: extent.x,
+            min_height: extent.y,
+        },
+        ..OrthographicProjection::default_3d()
+    })
+}
 
 #[derive(Debug, Clone, Default, States, PartialEq, Eq, Hash)]
 pub enum CamState {
@@ -45,6 +62,8 @@ pub enum CamMoveDir {
 struct Animations {
     animations: Vec<AnimationNodeIndex>,
     _graph: Handle<AnimationGraph>,
+    clip_game: Handle<AnimationClip>,
+    clip_editor: Handle<AnimationClip>,
 }
 
 pub struct CamCtrl;
@@ -56,6 +75,7 @@ impl Plugin for CamCtrl {
             .add_systems(PreStartup, setup)
             .add_systems(OnEnter(AppState::ToEditor), cam_move_edit)
             .add_systems(OnEnter(AppState::ToGame), cam_move_game)
+            .add_systems(Update, reframe_camera.run_if(resource_changed::<MapSize>))
             .add_systems(Update, cam_finished.run_if(
                 in_state(CamState::Moving(CamMoveDir::MoveToEditor))
                 .or(in_state(CamState::Moving(CamMoveDir::MoveToGame)))));
@@ -66,67 +86,12 @@ fn setup(
     mut animation_clips: ResMut<Assets<AnimationClip>>,
     mut animation_graphs: ResMut<Assets<AnimationGraph>>,
     mut commands: Commands,
+    map_size: Res<MapSize>,
 ) {
     // Setup Camera Curve Transition Animations
-    let mut animation_clip_editor = AnimationClip::default();
-    let mut animation_clip_game = AnimationClip::default();
-    let animation_domain = interval(0.25, 1.0).unwrap();
     let animation_target_name1 = Name::new("CameraPan");
     let animation_target_id1 = AnimationTargetId::from_name(&animation_target_name1);
-    let easing = EaseFunction::QuadraticInOut;
-
-    let trans_curve1 = EasingCurve::new(
-        vec3(
-            CAMERA_START_LOC.translation.x,
-            CAMERA_START_LOC.translation.y,
-            CAMERA_START_LOC.translation.z,
-        ),
-        vec3(
-            CAMERA_EDITOR_LOC.translation.x,
-            CAMERA_EDITOR_LOC.translation.y,
-            CAMERA_EDITOR_LOC.translation.z - TILE_SCALE / 2.,
-        ),
-        easing,
-    )
-        .reparametrize_linear(animation_domain)
-        .expect("curve is domain-bouded, shouldn't fail");
-
-    let trans_curve2 = trans_curve1.clone().reverse().expect("Expecting reverse possible.");
-
-    let editor_quat = CAMERA_EDITOR_LOC
-        .clone()
-        .looking_at(Vec3::new(CAMERA_EDITOR.x, 0.0, CAMERA_EDITOR.z), -Vec3::Z);
-
-    let rot_curve1 = EasingCurve::new(
-        CAMERA_START_LOC
-            .clone()
-            .looking_at(Vec3::new(CAMERA_EDITOR.x, 0.0, CAMERA_EDITOR.z), Vec3::Y)
-            .rotation,
-            // .normalize(),
-        editor_quat.rotation,
-        easing,
-    )
-    .reparametrize_linear(animation_domain)
-    .expect("shouldn't fail...");
-
-    let rot_curve2 = rot_curve1.clone().reverse().expect("Expecting reverse possible.");
-
-    animation_clip_editor.add_curve_to_target(
-        animation_target_id1,
-        AnimatableCurve::new(animated_field!(Transform::translation), trans_curve1),
-    );
-    animation_clip_editor.add_curve_to_target(
-        animation_target_id1,
-        AnimatableCurve::new(animated_field!(Transform::rotation), rot_curve1),
-    );
-    animation_clip_game.add_curve_to_target(
-        animation_target_id1,
-        AnimatableCurve::new(animated_field!(Transform::translation), trans_curve2),
-    );
-    animation_clip_game.add_curve_to_target(
-        animation_target_id1,
-        AnimatableCurve::new(animated_field!(Transform::rotation), rot_curve2),
-    );
+    let (animation_clip_editor, animation_clip_game) = camera_clips(&map_size, animation_target_id1);
 
     let animation_clip_handle_editor = animation_clips.add(animation_clip_editor);
     let animation_clip_handle_game = animation_clips.add(animation_clip_game);
@@ -142,6 +107,8 @@ fn setup(
     commands.insert_resource(Animations {
         animations: clips,
         _graph: graph_handle.clone(),
+        clip_game: animation_clip_handle_game,
+        clip_editor: animation_clip_handle_editor,
     });
 
     let animation_player = AnimationPlayer::default();
@@ -154,15 +121,8 @@ fn setup(
         .spawn((
             Camera3d::default(),
             Camera::default(),
-            Projection::Orthographic(OrthographicProjection {
-                scaling_mode: ScalingMode::FixedVertical {
-                    viewport_height: TILE_SCALE * MAP_SIZE as f32,
-                },
-                ..OrthographicProjection::default_3d()
-            }),
-            CAMERA_START_LOC
-                .clone()
-                .looking_at(Vec3::new(CAMERA_EDITOR.x, 0.0, CAMERA_EDITOR.z), Vec3::Y),
+            camera_projection(&map_size),
+            camera_start_loc(&map_size),
             animation_target_name1.clone(),
             animation_player,
             AnimationGraphHandle(graph_handle.clone()),
@@ -175,6 +135,86 @@ fn setup(
     });
 }
 
+/// Build the (to editor, to game) camera transition clips for the given map
+fn camera_clips(map_size: &MapSize, target: AnimationTargetId) -> (AnimationClip, AnimationClip) {
+    let mut animation_clip_editor = AnimationClip::default();
+    let mut animation_clip_game = AnimationClip::default();
+    let animation_domain = interval(0.25, 1.0).unwrap();
+    let easing = EaseFunction::QuadraticInOut;
+    let start_loc = camera_start_loc(map_size);
+    let editor_loc = camera_editor_loc(map_size);
+
+    let trans_curve1 = EasingCurve::new(
+        start_loc.translation,
+        vec3(
+            editor_loc.translation.x,
+            editor_loc.translation.y,
+            editor_loc.translation.z - TILE_SCALE / 2.,
+        ),
+        easing,
+    )
+        .reparametrize_linear(animation_domain)
+        .expect("curve is domain-bouded, shouldn't fail");
+
+    let trans_curve2 = trans_curve1.clone().reverse().expect("Expecting reverse possible.");
+
+    let rot_curve1 = EasingCurve::new(
+        start_loc.rotation,
+        editor_loc.rotation,
+        easing,
+    )
+    .reparametrize_linear(animation_domain)
+    .expect("shouldn't fail...");
+
+    let rot_curve2 = rot_curve1.clone().reverse().expect("Expecting reverse possible.");
+
+    animation_clip_editor.add_curve_to_target(
+        target,
+        AnimatableCurve::new(animated_field!(Transform::translation), trans_curve1),
+    );
+    animation_clip_editor.add_curve_to_target(
+        target,
+        AnimatableCurve::new(animated_field!(Transform::rotation), rot_curve1),
+    );
+    animation_clip_game.add_curve_to_target(
+        target,
+        AnimatableCurve::new(animated_field!(Transform::translation), trans_curve2),
+    );
+    animation_clip_game.add_curve_to_target(
+        target,
+        AnimatableCurve::new(animated_field!(Transform::rotation), rot_curve2),
+    );
+
+    (animation_clip_editor, animation_clip_game)
+}
+
+/// Rebuild the camera framing and transitions when a map with different dimensions is loaded
+fn reframe_camera(
+    map_size: Res<MapSize>,
+    animations: Option<Res<Animations>>,
+    mut animation_clips: ResMut<Assets<AnimationClip>>,
+    mut cam_query: Query<(&mut Projection, &mut Transform, &AnimationTarget), With<Camera>>,
+    cam_state: Res<State<CamState>>,
+) {
+    let Some(animations) = animations else {
+        return;
+    };
+    let Ok((mut projection, mut transform, target)) = cam_query.single_mut() else {
+        return;
+    };
+
+    let (clip_editor, clip_game) = camera_clips(&map_size, target.id);
+    animation_clips.insert(&animations.clip_editor, clip_editor);
+    animation_clips.insert(&animations.clip_game, clip_game);
+
+    *projection = camera_projection(&map_size);
+    match cam_state.get() {
+        CamState::GameView => *transform = camera_start_loc(&map_size),
+        CamState::EditorView => *transform = camera_editor_loc(&map_size),
+        CamState::Moving(_) => (),
+    }
+}
+
 fn cam_move_edit(
     animations: Res<Animations>,
     mut cam_query: Query<&mut AnimationPlayer, With<Camera>>,
@@ -199,6 +239,7 @@ fn cam_move_game(
     mut cam_query: Query<&mut AnimationPlayer, With<Camera>>,
     mut cam_nextstate: ResMut<NextState<CamState>>,
     cam_state: Res<State<CamState>>,
+    mut app_nextstate: ResMut<NextState<AppState>>,
 ) {
     if cam_state.get() != &CamState::GameView 
     && cam_state.get() != &CamState::Moving(CamMoveDir::MoveToEditor)
@@ -207,6 +248,8 @@ fn cam_move_game(
         let mut player = cam_query.single_mut().expect("Camera not found.. ");
         player.stop_all();
         player.play(*animations.animations.get(0).expect("Animations not initatialized properly.. "));
+    } else if cam_state.get() == &CamState::GameView {
+        app_nextstate.set(AppState::InGame);
     }
 }
 
@@ -1,19 +1,21 @@
-use std::{
-    collections::HashMap,
-    env::current_dir,
-    fs::File,
-    io::{Read, Write},
-};
-
 use crate::{
+    map_validation::{validate_map, MapValidationError},
     tilemap::{
-        update_gametilemap, EnemyPath, EnemyTile, GameTilemap, MapState, TileLocation, TileType, UpdateColorMap, BLOCKED_TILE_COLOR, ENEMY_TILE_COLOR, GROUND_TILE_COLOR
+        EnemyPath, EnemyTile, GameTilemap, MapState, SetTile, TileType, UpdateColorMap, BLOCKED_TILE_COLOR, ENEMY_TILE_COLOR, GROUND_TILE_COLOR, MapSize
     }, ui::{button, ButtonType, MenuType, PreviousButtonState}, AppState
 };
 use bevy::{prelude::*, window::PrimaryWindow};
-use rfd::FileDialog;
-use serde::{Deserialize, Serialize};
-use serde_with::serde_as;
+use brush::{finish_brush, start_brush, track_brush, BrushDrag};
+use history::{EditHistory, MapEdit, MapSnapshot};
+use map_dialog::{
+    open_load_dialog, open_save_as_dialog, poll_load_dialog, poll_save_dialog, save_map, CurrentMapFile, MapFileStatus,
+};
+use path_tool::{extend_path, finish_path, start_path, PathDraft};
+
+pub mod brush;
+pub mod history;
+pub mod map_dialog;
+mod path_tool;
 
 // TODO improve MiniTile (add border, fix offset and movement)
 
@@ -27,24 +29,33 @@ struct SaveMapEvent;
 #[derive(Debug, Component, Event)]
 struct LoadMapEvent;
 
+#[derive(Debug, Component, Event)]
+struct SaveAsMapEvent;
+
 #[derive(Debug, Component, Event)]
 struct ClearMapEvent;
 
+#[derive(Debug, Component, Event)]
+struct UndoEvent;
+
+#[derive(Debug, Component, Event)]
+struct RedoEvent;
+
 #[derive(Debug, Component)]
 struct EditorUI;
 
 #[derive(Debug, Component)]
 pub struct MiniTile;
 
-#[serde_as]
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct SavedTileMap(#[serde_as(as = "Vec<(_, _)>")] pub HashMap<TileType, Vec<IVec2>>);
+#[derive(Debug, Component)]
+struct ValidationText;
 
-impl SavedTileMap {
-    pub fn new() -> Self {
-        SavedTileMap(HashMap::new())
-    }
-}
+#[derive(Debug, Component)]
+struct FileStatusText;
+
+/// Result of the latest map verification, `None` until a map has been verified
+#[derive(Debug, Resource, Default)]
+pub struct MapValidation(pub Option<Result<(), MapValidationError>>);
 
 
 /// Usage:
@@ -55,14 +66,54 @@ pub struct Editor;
 impl Plugin for Editor {
     fn build(&self, app: &mut App) {
         app.init_state::<MiniTileState>()
+            .init_state::<EditorTool>()
+            .init_resource::<PathDraft>()
+            .init_resource::<BrushDrag>()
+            .init_resource::<EditHistory>()
             .add_event::<SaveMapEvent>()
             .add_event::<LoadMapEvent>()
+            .add_event::<SaveAsMapEvent>()
             .add_event::<ClearMapEvent>()
+            .add_event::<UndoEvent>()
+            .add_event::<RedoEvent>()
+            .init_resource::<MapValidation>()
+            .init_resource::<MapFileStatus>()
+            .init_resource::<CurrentMapFile>()
             .add_systems(Update, setup)
             .add_systems(
                 Update,
-                (editor_buttons, save_map, load_map, clear_map).run_if(in_state(AppState::InEditor)),
+                (editor_buttons, clear_map, show_validation).run_if(in_state(AppState::InEditor)),
+            )
+            .add_systems(
+                Update,
+                (
+                    (save_map, open_save_as_dialog).chain(),
+                    open_load_dialog,
+                    poll_load_dialog,
+                    poll_save_dialog,
+                    show_file_status,
+                )
+                    .run_if(in_state(AppState::InEditor)),
+            )
+            .add_systems(
+                Update,
+                (history_shortcuts, apply_history).chain().run_if(in_state(AppState::InEditor)),
+            )
+            .add_systems(
+                Update,
+                finish_path.run_if(in_state(AppState::InEditor).and(in_state(EditorTool::Path))),
+            )
+            .add_systems(
+                Update,
+                finish_brush.run_if(
+                    in_state(AppState::InEditor)
+                        .and(in_state(EditorTool::Rectangle).or(in_state(EditorTool::Line))),
+                ),
             )
+            .add_observer(start_path)
+            .add_observer(extend_path)
+            .add_observer(start_brush)
+            .add_observer(track_brush)
             .add_systems(
                 Update,
                 minitile_cursor_follow.run_if(in_state(MiniTileState::Spawned)),
@@ -79,6 +130,21 @@ impl Plugin for Editor {
     }
 }
 
+/// Active editing tool
+/// Tile: paint the selected MiniTile one tile per click
+/// Rectangle / Line: press and drag between two tiles to paint the selected MiniTile across them
+/// Fill: paint the selected MiniTile over the clicked tile and every connected tile of the same type
+/// Path: press to place Start, drag to extend the route, release to place Finish
+#[derive(States, Debug, Clone, Hash, PartialEq, Eq, Default)]
+pub enum EditorTool {
+    #[default]
+    Tile,
+    Rectangle,
+    Line,
+    Fill,
+    Path,
+}
+
 #[derive(States, Debug, Clone, Hash, PartialEq, Eq, Default)]
 pub enum MiniTileState {
     Spawned,
@@ -192,6 +258,31 @@ fn setup(app_state: Res<State<AppState>>, mut commands: Commands, mut gtm: ResMu
                     },
                     children![
                         button("Clear", ButtonType::Menu(MenuType::Clear)),
+                        button("Draw Path", ButtonType::Menu(MenuType::Tool(EditorTool::Path))),
+                    ]
+                ),
+                // Brush Row
+                (
+                    Node {
+                        flex_direction: FlexDirection::Row,
+                        ..default()
+                    },
+                    children![
+                        button("Single", ButtonType::Menu(MenuType::Tool(EditorTool::Tile))),
+                        button("Rect", ButtonType::Menu(MenuType::Tool(EditorTool::Rectangle))),
+                        button("Line", ButtonType::Menu(MenuType::Tool(EditorTool::Line))),
+                        button("Fill", ButtonType::Menu(MenuType::Tool(EditorTool::Fill))),
+                    ]
+                ),
+                // Undo / Redo Row
+                (
+                    Node {
+                        flex_direction: FlexDirection::Row,
+                        ..default()
+                    },
+                    children![
+                        button("Undo", ButtonType::Menu(MenuType::Undo)),
+                        button("Redo", ButtonType::Menu(MenuType::Redo)),
                     ]
                 ),
                 // Seventh Row
@@ -202,9 +293,22 @@ fn setup(app_state: Res<State<AppState>>, mut commands: Commands, mut gtm: ResMu
                     },
                     children![
                         button("Save", ButtonType::Menu(MenuType::Save)),
+                        button("Save As", ButtonType::Menu(MenuType::SaveAs)),
                         button("Load", ButtonType::Menu(MenuType::Load)),
                     ]
                 ),
+                // Load / Save results
+                (
+                    Text::new(""),
+                    TextFont::from_font_size(14.0),
+                    FileStatusText,
+                ),
+                // Validation Results
+                (
+                    Text::new(""),
+                    TextFont::from_font_size(14.0),
+                    ValidationText,
+                ),
             ],
         ));
 
@@ -222,10 +326,15 @@ fn editor_buttons(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut minitile_state: ResMut<NextState<MiniTileState>>,
+    tool: Res<State<EditorTool>>,
+    mut tool_state: ResMut<NextState<EditorTool>>,
     minitile: Query<Entity, With<MiniTile>>,
     mut ev_save_map: EventWriter<SaveMapEvent>,
     mut ev_load_map: EventWriter<LoadMapEvent>,
+    mut ev_save_as: EventWriter<SaveAsMapEvent>,
     mut ev_clear_map: EventWriter<ClearMapEvent>,
+    mut ev_undo: EventWriter<UndoEvent>,
+    mut ev_redo: EventWriter<RedoEvent>,
 ) {
     for (button_type, mut _color, interaction, prev_butt_state) in buttons.iter_mut() {
 
@@ -238,7 +347,10 @@ fn editor_buttons(
                             commands.entity(mt).despawn();
                         }
 
-                        // then spawn a new one
+                        // then spawn a new one, keeping the active brush
+                        if tool.get() == &EditorTool::Path {
+                            tool_state.set(EditorTool::Tile);
+                        }
                         spawn_minitile(
                             &mut commands,
                             &mut meshes,
@@ -257,6 +369,14 @@ fn editor_buttons(
                     }
                     _ => (),
                 },
+                MenuType::SaveAs => match interaction {
+                    Interaction::Pressed => {
+                        if prev_butt_state.0 != Interaction::Pressed {
+                            ev_save_as.write(SaveAsMapEvent);
+                        }
+                    }
+                    _ => (),
+                },
                 MenuType::Load => match interaction {
                     Interaction::Pressed => {
                         // info!("LoadButton Pressed; prev_button State: {:?}",prev_butt_state.0);
@@ -275,6 +395,32 @@ fn editor_buttons(
                     },
                     _ => (),
                 }
+                MenuType::Tool(selected_tool) => match interaction {
+                    Interaction::Pressed => {
+                        // the path tool does not paint the MiniTile TileType
+                        if selected_tool == &EditorTool::Path {
+                            minitile_state.set(MiniTileState::Despawn);
+                        }
+                        tool_state.set(selected_tool.clone());
+                    },
+                    _ => (),
+                }
+                MenuType::Undo => match interaction {
+                    Interaction::Pressed => {
+                        if prev_butt_state.0 != Interaction::Pressed {
+                            ev_undo.write(UndoEvent);
+                        }
+                    },
+                    _ => (),
+                }
+                MenuType::Redo => match interaction {
+                    Interaction::Pressed => {
+                        if prev_butt_state.0 != Interaction::Pressed {
+                            ev_redo.write(RedoEvent);
+                        }
+                    },
+                    _ => (),
+                }
                 _ => (),
             },
         }
@@ -338,238 +484,180 @@ fn minitile_cursor_follow(
     }
 }
 
-fn save_map(tile_query: Query<(&TileType, &TileLocation)>, ev_save_map: EventReader<SaveMapEvent>) {
-    if ev_save_map.is_empty() {
-        return;
-    }
-
-    let tilemap: SavedTileMap = tile_query
-        .iter()
-        .map(|(tt, t_loc)| (tt.clone(), t_loc.clone().0))
-        .collect::<Vec<(TileType, IVec2)>>()
-        .into_iter()
-        .fold(SavedTileMap::new(), |mut acc, (tt, t_loc)| {
-            acc.0.entry(tt).or_insert_with(Vec::new).push(t_loc);
-            acc
-        });
-
-    if let Some(mut file) = File::create("maps/map_save.txt").ok() {
-        let _ = file.write(serde_json::to_string(&tilemap).unwrap().as_bytes());
-    } else {
-        info!("Unable to save file 'maps/map_save.txt'");
-    }
-}
-
-fn get_saved_map(
-    file: Option<&File>,
-) -> SavedTileMap {
-    let mut contents = String::new();
-
-    if let Some(f) = file {
-        // file provided
-        let mut f_file = f;
-        f_file.read_to_string(&mut contents)
-            .expect("unable to read file.. ");
-    } else {
-        // no file provided, open dialog
-        let cwd = current_dir().expect("unable to get current directory.. ");
-        let file_path = FileDialog::new()
-            .add_filter("text", &["txt"])
-            .set_directory(cwd.to_str().unwrap())
-            .pick_file()
-            .expect("unable to open file.. ");
-
-        let mut temp_bind = File::open(
-            file_path
-            .to_str()
-            .unwrap()
-        ).expect("unable to open file.. ");
-        temp_bind.read_to_string(&mut contents)
-            .expect("unable to read file.. ");
-    }
-
-    let tilemap: SavedTileMap =
-        serde_json::from_str(&contents)
-        .expect(&format!("Unable to parse file; file: {:?}", file));
-    tilemap
-}
-
-fn load_map(
-    mut ev_load_map: EventReader<LoadMapEvent>,
-    mut enemy_path: ResMut<EnemyPath>,
-    mut map_nextstate: ResMut<NextState<MapState>>,
-    mut gtm: ResMut<GameTilemap>,
-    mut ev_update_colormap: EventWriter<UpdateColorMap>,
-) {
-    if ev_load_map.is_empty() {
-        return;
-    }
-
-
-    let tilemap = get_saved_map(None);
-
-    // get file and read contents
-    // FIXME replace .expects with if let Some()
-
-    // format as GameTilemap
-    let mut new_gtm = GameTilemap::default();
-    for (tt, locs) in tilemap.0.iter() {
-        for loc in locs {
-            new_gtm.0.insert(*loc, tt.clone());
-        }
-    }
-
-    // update the gametilemap
-    update_gametilemap(
-        &mut gtm,
-        &mut enemy_path,
-        &new_gtm,
-        &mut map_nextstate,
-        &mut ev_update_colormap,
-    );
-
-    // clear event to not trigger this function again
-    ev_load_map.clear();
-}
-
+/// Validate the map and record the result for the EditorUI panel
 fn map_verify(
     gtm: Res<GameTilemap>,
+    map_size: Res<MapSize>,
+    mut validation: ResMut<MapValidation>,
     mut map_nextstate: ResMut<NextState<MapState>>,
 ) {
     // FIXME need to check latest loaded map (maybe temp version of GTM intead of game version of GTM)
-    // collect enemy positions
-    let mut enemy_tiles = gtm.0.iter()
-            .filter_map(|(loc, tt)| {
-                if let TileType::EnemyMap(enemy_tile) = tt {
-                    Some((loc, enemy_tile))
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<(&IVec2, &EnemyTile)>>();
-    
-    // find start
-    let start_idx = enemy_tiles.iter().position(|(_loc, et)| *et == &EnemyTile::Start);
-    if let Some(idx) = start_idx {
-        // pop the start location from enemy_tiles (saves 1 iteration?)
-        let start = enemy_tiles.remove(idx);
-        if check_valid_neighbor(start.0, &mut enemy_tiles) {
+    let result = validate_map(&gtm, &map_size);
+    match &result {
+        Ok(()) => {
             map_nextstate.set(MapState::Reloaded);
             info!("Map Valid");
         }
-        else {
+        Err(e) => {
             map_nextstate.set(MapState::VerifyFailed);
-            info!("Map Not Valid");
+            info!("{e}");
         }
     }
+    validation.0 = Some(result);
 }
 
-fn check_valid_neighbor(loc: &IVec2, tiles: &mut Vec<(&IVec2, &EnemyTile)>
-) -> bool {
-    // info!("Checking: {:?}", loc);
-    for (i_loc, tile) in tiles.clone() {
-        // info!("Checking Tile: {:?}, {:?}", i_loc, tile);
-        if ((loc.x - i_loc.x).abs() <= 1 && (loc.y - i_loc.y).abs() == 0) ||
-           ((loc.x - i_loc.x).abs() == 0 && (loc.y - i_loc.y).abs() <= 1)
-        {
-            if *tile == EnemyTile::Finish {
-                return true
-            } else {
-                let new_loc = i_loc;
-                if let Some(idx) = tiles.iter().position(|(l,t)| l == &i_loc && t == &tile) {
-                    tiles.remove(idx);
-                }
-                return check_valid_neighbor(new_loc, tiles);
-            }
+/// Show the latest validation result in the EditorUI panel
+fn show_validation(
+    validation: Res<MapValidation>,
+    mut text_query: Query<(&mut Text, Ref<ValidationText>)>,
+) {
+    for (mut text, validation_text) in text_query.iter_mut() {
+        if !validation.is_changed() && !validation_text.is_added() {
+            continue;
         }
+        text.0 = match &validation.0 {
+            None => String::new(),
+            Some(Ok(())) => "Map Valid".into(),
+            Some(Err(e)) => e.to_string(),
+        };
+    }
+}
+
+fn show_file_status(
+    status: Res<MapFileStatus>,
+    mut text_query: Query<(&mut Text, &mut TextColor, Ref<FileStatusText>)>,
+) {
+    for (mut text, mut color, status_text) in text_query.iter_mut() {
+        if !status.is_changed() && !status_text.is_added() {
+            continue;
+        }
+        (text.0, color.0) = match &status.0 {
+            None => (String::new(), Color::WHITE),
+            Some(Ok(msg)) => (msg.clone(), Color::WHITE),
+            Some(Err(msg)) => (msg.clone(), Color::srgb(0.9, 0.3, 0.3)),
+        };
     }
-    info!("No valid neighbor found");
-    return false;
 }
 
 fn clear_map(
     ev_clear_map: EventReader<ClearMapEvent>,
     mut gtm: ResMut<GameTilemap>,
+    mut enemy_path: ResMut<EnemyPath>,
+    map_size: Res<MapSize>,
+    mut history: ResMut<EditHistory>,
     mut ev_update_colormap: EventWriter<UpdateColorMap>,
 ) {
     if ev_clear_map.is_empty() {
         return
     }
 
+    let before = MapSnapshot::capture(&gtm, &enemy_path, &map_size);
+
     // clear GTM
     for (_loc, tt) in gtm.0.iter_mut() {
         *tt = TileType::Free;
     }
+    enemy_path.0 = Some(vec![]);
+
+    history.record(MapEdit::diff(
+        "Clear",
+        &before,
+        &MapSnapshot::capture(&gtm, &enemy_path, &map_size),
+    ));
 
     // update map visuals
     ev_update_colormap.write(UpdateColorMap);
+}
 
+/// Ctrl+Z undoes the latest edit and Ctrl+Shift+Z redoes it
+fn history_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut ev_undo: EventWriter<UndoEvent>,
+    mut ev_redo: EventWriter<RedoEvent>,
+) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+        || !keys.just_pressed(KeyCode::KeyZ)
+    {
+        return;
+    }
 
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        ev_redo.write(RedoEvent);
+    } else {
+        ev_undo.write(UndoEvent);
+    }
+}
+
+/// Apply undo and redo requests from the EditHistory
+fn apply_history(
+    mut ev_undo: EventReader<UndoEvent>,
+    mut ev_redo: EventReader<RedoEvent>,
+    mut history: ResMut<EditHistory>,
+    mut gtm: ResMut<GameTilemap>,
+    mut enemy_path: ResMut<EnemyPath>,
+    mut map_size: ResMut<MapSize>,
+    mut map_nextstate: ResMut<NextState<MapState>>,
+    mut ev_update_colormap: EventWriter<UpdateColorMap>,
+    mut ev_set_tile: EventWriter<SetTile>,
+) {
+    let mut edits = vec![];
+    for _ev in ev_undo.read() {
+        edits.extend(history.undo());
+    }
+    for _ev in ev_redo.read() {
+        edits.extend(history.redo());
+    }
+    if edits.is_empty() {
+        return;
+    }
+
+    for edit in edits {
+        info!("Applying {} edit", edit.label);
+        edit.apply_resize(&mut gtm);
+        // painted path tiles are walked into the route by `apply_set_tile`, as when first applied
+        ev_set_tile.write_batch(edit.set_tiles());
+        if let Some([_before, path]) = edit.path {
+            enemy_path.0 = Some(path);
+        }
+        if let Some([_before, size]) = edit.size {
+            map_size.set_if_neq(MapSize(size));
+        }
+    }
+
+    ev_update_colormap.write(UpdateColorMap);
+    map_nextstate.set(MapState::NeedsVerify);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{map_file::MapFile, map_validation::MapProblem};
+    use map_dialog::read_map_file;
+    use std::path::Path;
 
     #[test]
     fn test_map_validation() {
         // load in saved maps
-        let simp = get_saved_map(Some(
-            &File::open("maps/simple1.txt").expect("no simple1.txt map found")
-        ));
-        let spiral = get_saved_map(Some(
-            &File::open("maps/spiral.txt").expect("no simple1.txt map found")
-        ));
-        let ground = get_saved_map(Some(
-            &File::open("maps/ground.txt").expect("no simple1.txt map found")
-        ));
+        let simp = read_map_file(Path::new("maps/simple1.txt")).expect("unable to load simple1.txt");
+        let spiral = read_map_file(Path::new("maps/spiral.txt")).expect("unable to load spiral.txt");
+        let ground = read_map_file(Path::new("maps/ground.txt")).expect("unable to load ground.txt");
 
-        let simp_tm = simp.0.iter()
-            .filter_map(|(tt, v_loc)| match tt {
-                TileType::EnemyMap(enemy_tile) => {
-                    Some(v_loc.iter().map(move |loc| (loc, enemy_tile)).into_iter())
-                },
-                _ => None
-            })
-            .flatten()
-            .collect::<Vec<(&IVec2, &EnemyTile)>>();
+        let size = |map: &MapFile| MapSize(IVec2::new(map.width, map.height));
 
+        assert_eq!(validate_map(&simp.to_gametilemap(), &size(&simp)), Ok(()));
 
-        let spir_tm = spiral.0.iter()
-            .filter_map(|(tt, v_loc)| match tt {
-                TileType::EnemyMap(enemy_tile) => {
-                    Some(v_loc.iter().map(move |loc| (loc, enemy_tile)).into_iter())
-                },
-                _ => None
-            })
-            .flatten()
-            .collect::<Vec<(&IVec2, &EnemyTile)>>();
-
-        let grnd_tm = ground.0.iter()
-            .filter_map(|(tt, v_loc)| match tt {
-                TileType::EnemyMap(enemy_tile) => {
-                    Some(v_loc.iter().map(move |loc| (loc, enemy_tile)).into_iter())
-                },
-                _ => None
-            })
-            .flatten()
-            .collect::<Vec<(&IVec2, &EnemyTile)>>();
-
-
-        for mut enemy_tiles in [simp_tm, spir_tm] {
-            let start_idx = enemy_tiles.iter().position(|(_loc, et)| *et == &EnemyTile::Start);
-            if let Some(idx) = start_idx {
-                // pop the start location from enemy_tiles (saves 1 iteration?)
-                let start = enemy_tiles.remove(idx);
-                assert!(check_valid_neighbor(&start.0, &mut enemy_tiles));
-            } else {
-                assert!(false);
-            }
-        }
+        // spiral uses straight pieces on its corners until the path is auto-tiled
+        let mut spiral_gtm = spiral.to_gametilemap();
+        let spiral_problems = validate_map(&spiral_gtm, &size(&spiral))
+            .expect_err("spiral corners are not shaped");
+        assert!(spiral_problems.0.iter().all(|p| matches!(p, MapProblem::ShapeMismatch { .. })));
+        spiral_gtm.autotile_path(&spiral.path);
+        assert_eq!(validate_map(&spiral_gtm, &size(&spiral)), Ok(()));
 
         // negative tests
-        let start_idx = grnd_tm.iter().position(|(_loc, et)| *et == &EnemyTile::Start);
-        assert_eq!(start_idx, None); 
+        assert_eq!(
+            validate_map(&ground.to_gametilemap(), &size(&ground)),
+            Err(MapValidationError(vec![MapProblem::MissingStart, MapProblem::MissingFinish]))
+        );
     }
 }
-
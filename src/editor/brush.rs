@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use super::{
+    history::{EditHistory, MapEdit, MapSnapshot},
+    EditorTool, MiniTile,
+};
+use crate::{
+    tilemap::{EnemyPath, GameTilemap, MapSize, SetTile, TileLocation, TileType, NEIGHBOURS},
+    AppState,
+};
+
+/// Tiles pressed and currently hovered while dragging a rectangle or line brush
+#[derive(Debug, Resource, Default)]
+pub(super) struct BrushDrag {
+    anchor: Option<IVec2>,
+    hovered: Option<IVec2>,
+}
+
+/// Every tile within the rectangle spanned by two corners
+pub fn rectangle(a: IVec2, b: IVec2) -> Vec<IVec2> {
+    let (min, max) = (a.min(b), a.max(b));
+    (min.x..=max.x)
+        .flat_map(|x| (min.y..=max.y).map(move |y| IVec2::new(x, y)))
+        .collect()
+}
+
+/// Tiles along the straight line from `a` to `b` (Bresenham)
+pub fn line(a: IVec2, b: IVec2) -> Vec<IVec2> {
+    let delta = (b - a).abs();
+    let step = (b - a).signum();
+    let mut err = delta.x - delta.y;
+    let mut loc = a;
+    let mut tiles = vec![a];
+
+    while loc != b {
+        let err2 = err * 2;
+        if err2 > -delta.y {
+            err -= delta.y;
+            loc.x += step.x;
+        }
+        if err2 < delta.x {
+            err += delta.x;
+            loc.y += step.y;
+        }
+        tiles.push(loc);
+    }
+    tiles
+}
+
+/// Tiles orthogonally connected to `start` sharing its TileType
+pub fn flood_fill(gtm: &GameTilemap, start: IVec2) -> Vec<IVec2> {
+    let Some(target) = gtm.0.get(&start) else {
+        return vec![];
+    };
+
+    let mut filled = HashSet::from([start]);
+    let mut stack = vec![start];
+    while let Some(loc) = stack.pop() {
+        for dir in NEIGHBOURS {
+            let next = loc + dir;
+            if gtm.0.get(&next) == Some(target) && filled.insert(next) {
+                stack.push(next);
+            }
+        }
+    }
+
+    let mut tiles = filled.into_iter().collect::<Vec<IVec2>>();
+    tiles.sort_by_key(|loc| (loc.x, loc.y));
+    tiles
+}
+
+/// Pressing a tile anchors a rectangle or line, or flood fills straight away
+pub(super) fn start_brush(
+    trigger: Trigger<Pointer<Pressed>>,
+    app_state: Res<State<AppState>>,
+    tool: Res<State<EditorTool>>,
+    tiles: Query<&TileLocation>,
+    mut drag: ResMut<BrushDrag>,
+    mut painter: BrushPainter,
+) {
+    if trigger.event().button != PointerButton::Primary || app_state.get() != &AppState::InEditor {
+        return;
+    }
+    let Ok(TileLocation(loc)) = tiles.get(trigger.target()) else {
+        return;
+    };
+
+    match tool.get() {
+        EditorTool::Rectangle | EditorTool::Line => {
+            drag.anchor = Some(*loc);
+            drag.hovered = Some(*loc);
+        }
+        EditorTool::Fill => {
+            let filled = flood_fill(&painter.gtm, *loc);
+            painter.paint("Fill", &filled);
+        }
+        _ => (),
+    }
+}
+
+pub(super) fn track_brush(
+    trigger: Trigger<Pointer<Over>>,
+    tiles: Query<&TileLocation>,
+    mut drag: ResMut<BrushDrag>,
+) {
+    if drag.anchor.is_none() {
+        return;
+    }
+    if let Ok(TileLocation(loc)) = tiles.get(trigger.target()) {
+        drag.hovered = Some(*loc);
+    }
+}
+
+/// Releasing the pointer paints the rectangle or line between the anchor and the hovered tile
+pub(super) fn finish_brush(
+    mouse: Res<ButtonInput<MouseButton>>,
+    tool: Res<State<EditorTool>>,
+    mut drag: ResMut<BrushDrag>,
+    mut painter: BrushPainter,
+) {
+    if !mouse.just_released(MouseButton::Left) {
+        return;
+    }
+    let (Some(anchor), Some(hovered)) = (drag.anchor.take(), drag.hovered.take()) else {
+        return;
+    };
+
+    match tool.get() {
+        EditorTool::Rectangle => painter.paint("Rectangle", &rectangle(anchor, hovered)),
+        EditorTool::Line => painter.paint("Line", &line(anchor, hovered)),
+        _ => (),
+    }
+}
+
+/// Applies the selected MiniTile TileType over many tiles as a single undoable edit
+#[derive(bevy::ecs::system::SystemParam)]
+pub(super) struct BrushPainter<'w, 's> {
+    minitile: Query<'w, 's, &'static TileType, With<MiniTile>>,
+    gtm: Res<'w, GameTilemap>,
+    enemy_path: Res<'w, EnemyPath>,
+    map_size: Res<'w, MapSize>,
+    history: ResMut<'w, EditHistory>,
+    ev_set_tile: EventWriter<'w, SetTile>,
+}
+
+impl BrushPainter<'_, '_> {
+    fn paint(&mut self, label: &'static str, locs: &[IVec2]) {
+        let Ok(selected_tt) = self.minitile.single().copied() else {
+            info!("Select a tile type before using the {label} brush");
+            return;
+        };
+
+        // record the path tiles reshaped by the retraced route along with the painted ones
+        let before = MapSnapshot::capture(&self.gtm, &self.enemy_path, &self.map_size);
+        let after = before.painted(locs, selected_tt);
+
+        self.history.record(MapEdit::diff(label, &before, &after));
+        self.ev_set_tile
+            .write_batch(locs.iter().map(|loc| SetTile { loc: *loc, tile: selected_tt }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rectangle_covers_both_corners() {
+        let tiles = rectangle(IVec2::new(3, 1), IVec2::new(1, 2));
+        assert_eq!(tiles.len(), 6);
+        assert!(tiles.contains(&IVec2::new(1, 1)) && tiles.contains(&IVec2::new(3, 2)));
+    }
+
+    #[test]
+    fn line_is_straight_and_inclusive() {
+        assert_eq!(
+            line(IVec2::new(0, 0), IVec2::new(3, 0)),
+            (0..=3).map(|x| IVec2::new(x, 0)).collect::<Vec<IVec2>>()
+        );
+        assert_eq!(
+            line(IVec2::new(2, 2), IVec2::new(0, 0)),
+            vec![IVec2::new(2, 2), IVec2::new(1, 1), IVec2::new(0, 0)]
+        );
+        assert_eq!(line(IVec2::new(0, 0), IVec2::new(4, 2)).len(), 5);
+    }
+
+    #[test]
+    fn flood_fill_stops_at_other_tiles() {
+        let mut gtm = GameTilemap::new(IVec2::new(4, 4));
+        for y in 0..4 {
+            gtm.0.insert(IVec2::new(2, y), TileType::Blocked);
+        }
+        assert_eq!(flood_fill(&gtm, IVec2::new(0, 0)).len(), 8);
+        assert_eq!(flood_fill(&gtm, IVec2::new(2, 3)).len(), 4);
+        assert!(flood_fill(&gtm, IVec2::new(9, 9)).is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::prelude::*;
+
+use crate::tilemap::{EnemyPath, GameTilemap, MapSize, SetTile, TileChanged, TileType};
+
+/// Number of edits kept for undo, the oldest edit is dropped past this
+pub const HISTORY_LIMIT: usize = 100;
+
+/// Tile before and after an edit, `None` when the tile does not exist in the map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileChange {
+    pub loc: IVec2,
+    pub before: Option<TileType>,
+    pub after: Option<TileType>,
+}
+
+/// Map state captured before a multi tile edit so it can be diffed afterwards
+#[derive(Debug, Clone)]
+pub struct MapSnapshot {
+    pub tiles: HashMap<IVec2, TileType>,
+    pub path: Vec<IVec2>,
+    pub size: IVec2,
+}
+
+impl MapSnapshot {
+    pub fn capture(gtm: &GameTilemap, enemy_path: &EnemyPath, map_size: &MapSize) -> Self {
+        MapSnapshot {
+            tiles: gtm.0.clone(),
+            path: enemy_path.tiles().to_vec(),
+            size: map_size.0,
+        }
+    }
+
+    /// Map after painting `tile` over `locs` with SetTile, including the path retraced and auto-tiled afterwards
+    pub fn painted(&self, locs: &[IVec2], tile: TileType) -> MapSnapshot {
+        let mut gtm = GameTilemap(self.tiles.clone());
+        let changes = locs
+            .iter()
+            .filter_map(|loc| gtm.set_tile(*loc, tile))
+            .collect::<Vec<TileChanged>>();
+        let mut enemy_path = EnemyPath(Some(self.path.clone()));
+        if enemy_path.needs_retrace(&changes) {
+            enemy_path.retrace(&gtm);
+            gtm.autotile_path(enemy_path.tiles());
+        }
+        MapSnapshot {
+            tiles: gtm.0,
+            path: enemy_path.tiles().to_vec(),
+            size: self.size,
+        }
+    }
+}
+
+/// Reversible map edit, tile changes plus the path and size when they changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapEdit {
+    pub label: &'static str,
+    pub tiles: Vec<TileChange>,
+    /// [before, after]
+    pub path: Option<[Vec<IVec2>; 2]>,
+    /// [before, after]
+    pub size: Option<[IVec2; 2]>,
+}
+
+impl MapEdit {
+    pub fn tile(loc: IVec2, before: TileType, after: TileType) -> Self {
+        MapEdit {
+            label: "Tile",
+            tiles: vec![TileChange {
+                loc,
+                before: Some(before),
+                after: Some(after),
+            }],
+            path: None,
+            size: None,
+        }
+    }
+
+    /// Every difference between two snapshots, tiles in a stable order
+    pub fn diff(label: &'static str, before: &MapSnapshot, after: &MapSnapshot) -> Self {
+        let mut locs = before
+            .tiles
+            .keys()
+            .chain(after.tiles.keys())
+            .copied()
+            .collect::<HashSet<IVec2>>()
+            .into_iter()
+            .collect::<Vec<IVec2>>();
+        locs.sort_by_key(|loc| (loc.x, loc.y));
+
+        MapEdit {
+            label,
+            tiles: locs
+                .into_iter()
+                .map(|loc| TileChange {
+                    loc,
+                    before: before.tiles.get(&loc).copied(),
+                    after: after.tiles.get(&loc).copied(),
+                })
+                .filter(|change| change.before != change.after)
+                .collect(),
+            path: (before.path != after.path).then(|| [before.path.clone(), after.path.clone()]),
+            size: (before.size != after.size).then_some([before.size, after.size]),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty() && self.path.is_none() && self.size.is_none()
+    }
+
+    /// The edit which reverts this one
+    pub fn inverse(&self) -> Self {
+        MapEdit {
+            label: self.label,
+            tiles: self
+                .tiles
+                .iter()
+                .map(|change| TileChange {
+                    loc: change.loc,
+                    before: change.after,
+                    after: change.before,
+                })
+                .collect(),
+            path: self.path.clone().map(|[before, after]| [after, before]),
+            size: self.size.map(|[before, after]| [after, before]),
+        }
+    }
+
+    /// Requests changing the tiles which exist both before and after the edit
+    pub fn set_tiles(&self) -> Vec<SetTile> {
+        self.tiles
+            .iter()
+            .filter(|change| change.before.is_some())
+            .filter_map(|change| change.after.map(|tile| SetTile { loc: change.loc, tile }))
+            .collect()
+    }
+
+    /// Add and remove the tiles of a resized map, their entities are respawned along with the MapSize
+    pub fn apply_resize(&self, gtm: &mut GameTilemap) {
+        for change in &self.tiles {
+            match (change.before, change.after) {
+                (None, Some(tt)) => {
+                    gtm.0.insert(change.loc, tt);
+                }
+                (Some(_), None) => {
+                    gtm.0.remove(&change.loc);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Write the tile changes into the GameTilemap, path and size are left to the caller
+    pub fn apply_tiles(&self, gtm: &mut GameTilemap) {
+        self.apply_resize(gtm);
+        for SetTile { loc, tile } in self.set_tiles() {
+            gtm.set_tile(loc, tile);
+        }
+    }
+}
+
+/// Bounded undo and redo stacks of map edits
+#[derive(Debug, Resource, Default)]
+pub struct EditHistory {
+    undo: VecDeque<MapEdit>,
+    redo: Vec<MapEdit>,
+}
+
+impl EditHistory {
+    /// Record a new edit, dropping any redo history
+    pub fn record(&mut self, edit: MapEdit) {
+        if edit.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push_back(edit);
+        if self.undo.len() > HISTORY_LIMIT {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Edit to apply in order to undo the latest edit
+    pub fn undo(&mut self) -> Option<MapEdit> {
+        let edit = self.undo.pop_back()?;
+        let inverse = edit.inverse();
+        self.redo.push(edit);
+        Some(inverse)
+    }
+
+    /// Edit to apply in order to redo the latest undone edit
+    pub fn redo(&mut self) -> Option<MapEdit> {
+        let edit = self.redo.pop()?;
+        self.undo.push_back(edit.clone());
+        Some(edit)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::EnemyTile;
+
+    fn snapshot(gtm: &GameTilemap, path: Vec<IVec2>) -> MapSnapshot {
+        MapSnapshot::capture(gtm, &EnemyPath(Some(path)), &MapSize(IVec2::new(3, 3)))
+    }
+
+    #[test]
+    fn diff_undo_redo_round_trip() {
+        let mut gtm = GameTilemap::new(IVec2::new(3, 3));
+        let before = snapshot(&gtm, vec![]);
+        let path = vec![IVec2::new(0, 0), IVec2::new(1, 0)];
+        gtm.autotile_path(&path);
+        gtm.0.insert(IVec2::new(2, 2), TileType::Blocked);
+        let after = snapshot(&gtm, path.clone());
+
+        let mut history = EditHistory::default();
+        history.record(MapEdit::diff("Path", &before, &after));
+        assert_eq!(history.undo.back().unwrap().tiles.len(), 3);
+
+        let undo = history.undo().unwrap();
+        undo.apply_tiles(&mut gtm);
+        assert_eq!(gtm.0, before.tiles);
+        assert_eq!(undo.path, Some([path.clone(), vec![]]));
+        assert!(!history.can_undo() && history.can_redo());
+
+        history.redo().unwrap().apply_tiles(&mut gtm);
+        assert_eq!(gtm.0, after.tiles);
+        assert_eq!(gtm.0[&IVec2::new(0, 0)], TileType::EnemyMap(EnemyTile::Start));
+    }
+
+    #[test]
+    fn painted_edits_include_the_retraced_path() {
+        let mut gtm = GameTilemap::new(IVec2::new(3, 3));
+        let path = vec![IVec2::new(0, 0), IVec2::new(0, 1), IVec2::new(0, 2)];
+        gtm.autotile_path(&path);
+        let before = snapshot(&gtm, path.clone());
+
+        let off_route = MapEdit::diff("Rectangle", &before, &before.painted(&[IVec2::new(2, 2)], TileType::Blocked));
+        assert_eq!(off_route.tiles.len(), 1);
+        assert_eq!(off_route.path, None);
+
+        // breaking the path empties the route, undoing the edit brings it back
+        let broken = MapEdit::diff("Line", &before, &before.painted(&[IVec2::new(0, 1)], TileType::Blocked));
+        assert_eq!(broken.path, Some([path.clone(), vec![]]));
+        let undo = broken.inverse();
+        undo.apply_tiles(&mut gtm);
+        assert_eq!(gtm.0, before.tiles);
+        assert_eq!(undo.path, Some([vec![], path]));
+    }
+
+    #[test]
+    fn history_is_bounded_and_record_clears_redo() {
+        let mut history = EditHistory::default();
+        for _ in 0..HISTORY_LIMIT + 5 {
+            history.record(MapEdit::tile(IVec2::ZERO, TileType::Free, TileType::Blocked));
+        }
+        assert_eq!(history.undo.len(), HISTORY_LIMIT);
+
+        history.undo();
+        assert!(history.can_redo());
+        history.record(MapEdit::tile(IVec2::ONE, TileType::Free, TileType::Blocked));
+        assert!(!history.can_redo());
+
+        // edits which change nothing are not recorded
+        let gtm = GameTilemap::new(IVec2::new(3, 3));
+        history.record(MapEdit::diff("Noop", &snapshot(&gtm, vec![]), &snapshot(&gtm, vec![])));
+        assert_eq!(history.undo.len(), HISTORY_LIMIT);
+    }
+}
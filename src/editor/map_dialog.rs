@@ -0,0 +1,293 @@
+use std::{
+    env::current_dir,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    prelude::*,
+    tasks::{block_on, poll_once, AsyncComputeTaskPool, Task},
+};
+use rfd::AsyncFileDialog;
+
+use super::{
+    history::{EditHistory, MapEdit, MapSnapshot},
+    LoadMapEvent, SaveAsMapEvent, SaveMapEvent,
+};
+use crate::{
+    map_file::{parse_map, serialize_map, MapFile, MapFileError, SavedTileMap},
+    sidecar::write_atomically,
+    tilemap::{update_gametilemap, EnemyPath, GameTilemap, MapSize, MapState, UpdateColorMap},
+};
+
+/// Reason a map could not be loaded or saved, the current map is left as it was
+#[derive(Debug)]
+pub enum MapIoError {
+    /// The file dialog was closed without picking a file
+    Cancelled,
+    Io(io::Error),
+    Map(MapFileError),
+}
+
+impl fmt::Display for MapIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapIoError::Cancelled => write!(f, "no file selected"),
+            MapIoError::Io(e) => write!(f, "unable to access map file: {e}"),
+            MapIoError::Map(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for MapIoError {}
+
+impl From<io::Error> for MapIoError {
+    fn from(e: io::Error) -> Self {
+        MapIoError::Io(e)
+    }
+}
+
+impl From<MapFileError> for MapIoError {
+    fn from(e: MapFileError) -> Self {
+        MapIoError::Map(e)
+    }
+}
+
+/// Outcome of the latest load or save, shown below the Save and Load buttons
+#[derive(Debug, Resource, Default)]
+pub struct MapFileStatus(pub Option<Result<String, String>>);
+
+/// File the map in the editor was last loaded from or saved to, `None` for a new map
+#[derive(Debug, Resource, Default)]
+pub struct CurrentMapFile(pub Option<PathBuf>);
+
+/// File dialog picking a map to load, followed by reading and parsing it
+#[derive(Component)]
+pub(super) struct LoadMapTask(Task<Result<(PathBuf, MapFile), MapIoError>>);
+
+/// File dialog picking where to save the map, followed by writing it
+#[derive(Component)]
+pub(super) struct SaveMapTask(Task<Result<PathBuf, MapIoError>>);
+
+/// Read and parse a map file without touching the current map
+pub fn read_map_file(path: &Path) -> Result<MapFile, MapIoError> {
+    let contents = fs::read_to_string(path)?;
+    Ok(parse_map(&contents)?)
+}
+
+/// Written through a temporary file so a failed save never leaves a truncated map behind
+pub fn write_map_file(path: &Path, map_file: &MapFile) -> Result<(), MapIoError> {
+    let contents = serialize_map(map_file)?;
+    Ok(write_atomically(path, &contents)?)
+}
+
+/// Map in the editor as it would be written to file
+pub fn capture_map_file(gtm: &GameTilemap, enemy_path: &EnemyPath, map_size: &MapSize) -> MapFile {
+    // the GameTilemap is the source of truth, tile entities only mirror it
+    MapFile::new(
+        map_size.width(),
+        map_size.height(),
+        SavedTileMap::from(gtm),
+        enemy_path.tiles().to_vec(),
+    )
+}
+
+fn map_dialog() -> AsyncFileDialog {
+    let dialog = AsyncFileDialog::new().add_filter("text", &["txt"]);
+    match current_dir() {
+        Ok(cwd) => dialog.set_directory(cwd),
+        Err(_) => dialog,
+    }
+}
+
+/// Open the load dialog off the main loop, only one map dialog is open at a time
+pub(super) fn open_load_dialog(
+    mut commands: Commands,
+    mut ev_load_map: EventReader<LoadMapEvent>,
+    load_tasks: Query<(), With<LoadMapTask>>,
+    save_tasks: Query<(), With<SaveMapTask>>,
+) {
+    if ev_load_map.read().count() == 0 || !load_tasks.is_empty() || !save_tasks.is_empty() {
+        return;
+    }
+
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        let file = map_dialog().pick_file().await.ok_or(MapIoError::Cancelled)?;
+        let path = file.path().to_path_buf();
+        let map_file = read_map_file(&path)?;
+        Ok((path, map_file))
+    });
+    commands.spawn(LoadMapTask(task));
+}
+
+/// Save over the current map file, a map without one is saved through the Save As dialog instead
+pub(super) fn save_map(
+    mut ev_save_map: EventReader<SaveMapEvent>,
+    mut ev_save_as: EventWriter<SaveAsMapEvent>,
+    current_file: Res<CurrentMapFile>,
+    mut status: ResMut<MapFileStatus>,
+    gtm: Res<GameTilemap>,
+    enemy_path: Res<EnemyPath>,
+    map_size: Res<MapSize>,
+) {
+    if ev_save_map.read().count() == 0 {
+        return;
+    }
+    let Some(path) = &current_file.0 else {
+        ev_save_as.write(SaveAsMapEvent);
+        return;
+    };
+
+    status.0 = Some(match write_map_file(path, &capture_map_file(&gtm, &enemy_path, &map_size)) {
+        Ok(()) => {
+            info!("Saved map {}", path.display());
+            Ok(format!("Saved {}", path.display()))
+        }
+        Err(e) => {
+            warn!("Unable to save map: {e}");
+            Err(format!("Save failed: {e}"))
+        }
+    });
+}
+
+/// Open the save dialog off the main loop, the map is captured when the dialog opens
+pub(super) fn open_save_as_dialog(
+    mut commands: Commands,
+    mut ev_save_as: EventReader<SaveAsMapEvent>,
+    load_tasks: Query<(), With<LoadMapTask>>,
+    save_tasks: Query<(), With<SaveMapTask>>,
+    gtm: Res<GameTilemap>,
+    enemy_path: Res<EnemyPath>,
+    map_size: Res<MapSize>,
+) {
+    if ev_save_as.read().count() == 0 || !load_tasks.is_empty() || !save_tasks.is_empty() {
+        return;
+    }
+
+    let map_file = capture_map_file(&gtm, &enemy_path, &map_size);
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        let file = map_dialog()
+            .set_file_name("map.txt")
+            .save_file()
+            .await
+            .ok_or(MapIoError::Cancelled)?;
+        let path = file.path().to_path_buf();
+        write_map_file(&path, &map_file)?;
+        Ok(path)
+    });
+    commands.spawn(SaveMapTask(task));
+}
+
+/// Apply a loaded map once its dialog task finishes, failures only update the status
+pub(super) fn poll_load_dialog(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut LoadMapTask)>,
+    mut status: ResMut<MapFileStatus>,
+    mut current_file: ResMut<CurrentMapFile>,
+    mut gtm: ResMut<GameTilemap>,
+    mut map_size: ResMut<MapSize>,
+    mut enemy_path: ResMut<EnemyPath>,
+    mut history: ResMut<EditHistory>,
+    mut map_nextstate: ResMut<NextState<MapState>>,
+    mut ev_update_colormap: EventWriter<UpdateColorMap>,
+) {
+    for (ent, mut task) in tasks.iter_mut() {
+        let Some(result) = block_on(poll_once(&mut task.0)) else {
+            continue;
+        };
+        commands.entity(ent).despawn();
+
+        let (path, map_file) = match result {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                warn!("Unable to load map: {e}");
+                status.0 = Some(Err(format!("Load failed: {e}")));
+                continue;
+            }
+        };
+
+        let before = MapSnapshot::capture(&gtm, &enemy_path, &map_size);
+        update_gametilemap(
+            &mut gtm,
+            &mut map_size,
+            &mut enemy_path,
+            &map_file,
+            &mut map_nextstate,
+            &mut ev_update_colormap,
+        );
+        let after = MapSnapshot::capture(&gtm, &enemy_path, &map_size);
+        history.record(MapEdit::diff("Load", &before, &after));
+
+        info!("Loaded map {}", path.display());
+        status.0 = Some(Ok(format!("Loaded {}", path.display())));
+        current_file.0 = Some(path);
+    }
+}
+
+pub(super) fn poll_save_dialog(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut SaveMapTask)>,
+    mut status: ResMut<MapFileStatus>,
+    mut current_file: ResMut<CurrentMapFile>,
+) {
+    for (ent, mut task) in tasks.iter_mut() {
+        let Some(result) = block_on(poll_once(&mut task.0)) else {
+            continue;
+        };
+        commands.entity(ent).despawn();
+
+        status.0 = Some(match result {
+            Ok(path) => {
+                info!("Saved map {}", path.display());
+                let msg = format!("Saved {}", path.display());
+                current_file.0 = Some(path);
+                Ok(msg)
+            }
+            Err(e) => {
+                warn!("Unable to save map: {e}");
+                Err(format!("Save failed: {e}"))
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sidecar::test_file_path;
+
+    #[test]
+    fn read_errors_are_recoverable() {
+        assert!(matches!(
+            read_map_file(Path::new("maps/does_not_exist.txt")),
+            Err(MapIoError::Io(_))
+        ));
+
+        let path = test_file_path("malformed_map.txt");
+        fs::write(&path, "not a map").unwrap();
+        assert!(matches!(read_map_file(&path), Err(MapIoError::Map(MapFileError::Parse(_)))));
+
+        let map_file = read_map_file(Path::new("maps/simple1.txt")).unwrap();
+        write_map_file(&path, &map_file).unwrap();
+        assert_eq!(read_map_file(&path).unwrap(), map_file);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn writes_replace_the_file_atomically() {
+        let path = test_file_path("atomic_map.txt");
+        let simple = read_map_file(Path::new("maps/simple1.txt")).unwrap();
+        let spiral = read_map_file(Path::new("maps/spiral.txt")).unwrap();
+
+        write_map_file(&path, &simple).unwrap();
+        write_map_file(&path, &spiral).unwrap();
+        assert_eq!(read_map_file(&path).unwrap(), spiral);
+        assert!(!path.with_extension("txt.tmp").exists());
+        fs::remove_file(&path).unwrap();
+
+        // a failed write leaves nothing behind
+        let missing_dir = test_file_path("missing_dir").join("map.txt");
+        assert!(matches!(write_map_file(&missing_dir, &simple), Err(MapIoError::Io(_))));
+        assert!(!missing_dir.exists());
+    }
+}
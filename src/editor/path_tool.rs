@@ -0,0 +1,175 @@
+use bevy::prelude::*;
+
+use super::{
+    history::{EditHistory, MapEdit, MapSnapshot},
+    EditorTool,
+};
+use crate::{
+    tilemap::{EnemyPath, GameTilemap, MapSize, MapState, SetTile, TileLocation, TileType},
+    AppState,
+};
+
+/// Route being drawn with the path tool, `drawing` while the pointer is held down
+#[derive(Debug, Resource, Default)]
+pub(super) struct PathDraft {
+    drawing: bool,
+    route: Vec<IVec2>,
+    /// Tile each step of the route was drawn over, restored when backtracking
+    replaced: Vec<TileType>,
+    /// Map before the route was started, recorded as one edit on release
+    before: Option<MapSnapshot>,
+}
+
+/// Pressing a tile replaces the current path with a new one starting on that tile
+pub(super) fn start_path(
+    trigger: Trigger<Pointer<Pressed>>,
+    app_state: Res<State<AppState>>,
+    tool: Res<State<EditorTool>>,
+    tiles: Query<&TileLocation>,
+    map_size: Res<MapSize>,
+    mut draft: ResMut<PathDraft>,
+    gtm: Res<GameTilemap>,
+    mut enemy_path: ResMut<EnemyPath>,
+    mut ev_set_tile: EventWriter<SetTile>,
+) {
+    if trigger.event().button != PointerButton::Primary
+        || app_state.get() != &AppState::InEditor
+        || tool.get() != &EditorTool::Path
+    {
+        return;
+    }
+    let Ok(loc) = tiles.get(trigger.target()) else {
+        return;
+    };
+
+    draft.before = Some(MapSnapshot::capture(&gtm, &enemy_path, &map_size));
+    let mut old_path = gtm
+        .0
+        .iter()
+        .filter(|(tile_loc, tt)| matches!(tt, TileType::EnemyMap(_)) && **tile_loc != loc.0)
+        .map(|(tile_loc, _tt)| *tile_loc)
+        .collect::<Vec<IVec2>>();
+    old_path.sort_by_key(|loc| (loc.x, loc.y));
+    ev_set_tile.write_batch(old_path.into_iter().map(|loc| SetTile {
+        loc,
+        tile: TileType::Free,
+    }));
+
+    draft.drawing = true;
+    draft.route = vec![loc.0];
+    draft.replaced = vec![replaced_tile(&gtm, loc.0)];
+    enemy_path.0 = Some(draft.route.clone());
+}
+
+/// Dragging onto an orthogonally adjacent tile extends the route,
+/// dragging back onto the previous tile undoes the last segment
+pub(super) fn extend_path(
+    trigger: Trigger<Pointer<Over>>,
+    tiles: Query<&TileLocation>,
+    mut draft: ResMut<PathDraft>,
+    gtm: Res<GameTilemap>,
+    mut enemy_path: ResMut<EnemyPath>,
+    mut ev_set_tile: EventWriter<SetTile>,
+) {
+    if !draft.drawing {
+        return;
+    }
+    let Ok(TileLocation(loc)) = tiles.get(trigger.target()) else {
+        return;
+    };
+
+    let len = draft.route.len();
+    if len >= 2 && draft.route[len - 2] == *loc {
+        let removed = draft.route.pop().expect("route has at least 2 tiles");
+        let tile = draft.replaced.pop().expect("every step of the route replaced a tile");
+        ev_set_tile.write(SetTile { loc: removed, tile });
+    } else if draft.route.last().is_some_and(|last| (*last - *loc).abs().element_sum() == 1)
+        && !draft.route.contains(loc)
+        && !matches!(gtm.0.get(loc), Some(TileType::Tower(_)))
+    {
+        draft.route.push(*loc);
+        draft.replaced.push(replaced_tile(&gtm, *loc));
+    } else {
+        return;
+    }
+
+    // EnemyPath changes are auto-tiled into the GameTilemap
+    enemy_path.0 = Some(draft.route.clone());
+}
+
+/// Releasing the pointer places Finish on the last tile of the route
+pub(super) fn finish_path(
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut draft: ResMut<PathDraft>,
+    mut enemy_path: ResMut<EnemyPath>,
+    mut history: ResMut<EditHistory>,
+    mut map_nextstate: ResMut<NextState<MapState>>,
+    mut ev_set_tile: EventWriter<SetTile>,
+) {
+    if !draft.drawing || !mouse.just_released(MouseButton::Left) {
+        return;
+    }
+    draft.drawing = false;
+
+    // a path needs at least a Start and a Finish
+    if draft.route.len() < 2 {
+        let replaced = draft.replaced.drain(..).collect::<Vec<TileType>>();
+        ev_set_tile.write_batch(draft.route.drain(..).zip(replaced).map(|(loc, tile)| SetTile { loc, tile }));
+    }
+    // EnemyPath changes are auto-tiled into the GameTilemap
+    enemy_path.0 = Some(draft.route.clone());
+    map_nextstate.set(MapState::NeedsVerify);
+
+    if let Some(before) = draft.before.take() {
+        history.record(MapEdit::diff("Path", &before, &drawn_path(&before, &draft.route)));
+    }
+}
+
+/// Tile a step of the route is drawn over, tiles of the previous path count as Free since they are cleared
+fn replaced_tile(gtm: &GameTilemap, loc: IVec2) -> TileType {
+    match gtm.0.get(&loc) {
+        Some(TileType::EnemyMap(_)) | None => TileType::Free,
+        Some(tt) => *tt,
+    }
+}
+
+/// Map once a route drawn over `before` is in place, the previous path cleared and the route auto-tiled
+fn drawn_path(before: &MapSnapshot, route: &[IVec2]) -> MapSnapshot {
+    let mut gtm = GameTilemap(before.tiles.clone());
+    for tt in gtm.0.values_mut() {
+        if matches!(tt, TileType::EnemyMap(_)) {
+            *tt = TileType::Free;
+        }
+    }
+    gtm.autotile_path(route);
+    MapSnapshot {
+        tiles: gtm.0,
+        path: route.to_vec(),
+        size: before.size,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::EnemyTile;
+
+    #[test]
+    fn drawn_path_keeps_tiles_off_the_route() {
+        let mut gtm = GameTilemap::new(IVec2::new(3, 3));
+        gtm.autotile_path(&[IVec2::new(0, 0), IVec2::new(0, 1)]);
+        gtm.0.insert(IVec2::new(2, 0), TileType::Blocked);
+        gtm.0.insert(IVec2::new(2, 1), TileType::Blocked);
+        let before = MapSnapshot::capture(&gtm, &EnemyPath(None), &MapSize(IVec2::new(3, 3)));
+
+        // the route was drawn across (2, 1) and backtracked out of it
+        assert_eq!(replaced_tile(&gtm, IVec2::new(2, 1)), TileType::Blocked);
+        assert_eq!(replaced_tile(&gtm, IVec2::new(0, 1)), TileType::Free);
+        let route = [IVec2::new(1, 0), IVec2::new(2, 0)];
+        let after = drawn_path(&before, &route);
+        assert_eq!(after.tiles[&IVec2::new(0, 0)], TileType::Free);
+        assert_eq!(after.tiles[&IVec2::new(2, 0)], TileType::EnemyMap(EnemyTile::Finish));
+        assert_eq!(after.tiles[&IVec2::new(2, 1)], TileType::Blocked);
+        assert_eq!(after.path, route);
+    }
+}
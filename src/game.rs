@@ -0,0 +1,210 @@
+use bevy::prelude::*;
+
+use crate::AppState;
+use combat::{apply_damage, cycle_target_priority, tower_attack, CycleTargetPriority, DamageEnemy, EnemyKilled};
+use economy::{collect_rewards, sell_towers, SellTower, Wallet};
+use enemy::{
+    heal_enemies, load_enemy_definitions, move_enemies, regenerate_enemies, setup_enemy_assets, spawn_enemies,
+    split_enemies, EnemyCounter, EnemyReachedFinish, FlightRoute, SpawnEnemy,
+};
+use hud::{
+    hud_buttons, replay_buttons, spawn_hud, tower_panel_buttons, update_gold_text, update_lives_text, update_speed_text,
+    update_tower_panel, update_wave_text,
+};
+use outcome::{
+    check_game_over, despawn_end_screen, end_screen_buttons, game_undecided, lose_lives, spawn_end_screen, Lives,
+};
+use projectile::{move_projectiles, setup_projectile_assets, spawn_projectiles, FireProjectile};
+use replay::{
+    advance_tick, drop_player_commands, finish_replay, play_commands, play_replay, record_commands, save_replay,
+    start_replay, ActiveReplay, GameRng, PendingReplay, PlayReplayEvent, SaveReplayEvent, SimTick,
+};
+use save::{load_game, restore_game, save_game, LoadGameEvent, PendingSave, SaveGameEvent};
+use speed::{apply_game_speed, reset_game_speed, restore_virtual_time, speed_keys, GameSpeed};
+use status::{apply_status_effects, setup_status_assets, tick_status_effects, update_status_markers, ApplyStatus};
+use tower::{
+    build_towers, clear_tower_tiles, hide_ghost, hover_tile, place_tower, setup_tower_assets, unhover_tile,
+    update_ghost, BuildTower, FocusedTower, HoveredTile, SelectedTower,
+};
+use upgrade::{upgrade_towers, UpgradeTower};
+use wave::{
+    check_waves_cleared, load_waves, send_next_wave, tick_waves, AllWavesCleared, SendNextWave, WaveCleared,
+    WaveScheduler, WaveStarted,
+};
+
+pub mod combat;
+pub mod damage;
+pub mod economy;
+pub mod enemy;
+mod hud;
+pub mod outcome;
+pub mod projectile;
+pub mod replay;
+pub mod save;
+pub mod speed;
+pub mod status;
+pub mod tower;
+pub mod upgrade;
+pub mod wave;
+
+/// Marker for entities belonging to a running game
+/// They are despawned whenever a new game starts or the editor is opened
+#[derive(Debug, Component, Default)]
+pub struct GameScoped;
+
+/// Gameplay while in AppState::InGame
+pub struct Game;
+
+impl Plugin for Game {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WaveScheduler>()
+            .init_resource::<SelectedTower>()
+            .init_resource::<HoveredTile>()
+            .init_resource::<FocusedTower>()
+            .init_resource::<Wallet>()
+            .init_resource::<Lives>()
+            .init_resource::<PendingSave>()
+            .init_resource::<FlightRoute>()
+            .init_resource::<GameSpeed>()
+            .init_resource::<PendingReplay>()
+            .init_resource::<ActiveReplay>()
+            .init_resource::<GameRng>()
+            .init_resource::<SimTick>()
+            .init_resource::<EnemyCounter>()
+            .add_event::<SpawnEnemy>()
+            .add_event::<EnemyReachedFinish>()
+            .add_event::<SendNextWave>()
+            .add_event::<WaveStarted>()
+            .add_event::<WaveCleared>()
+            .add_event::<AllWavesCleared>()
+            .add_event::<BuildTower>()
+            .add_event::<DamageEnemy>()
+            .add_event::<EnemyKilled>()
+            .add_event::<FireProjectile>()
+            .add_event::<ApplyStatus>()
+            .add_event::<SellTower>()
+            .add_event::<UpgradeTower>()
+            .add_event::<SaveGameEvent>()
+            .add_event::<LoadGameEvent>()
+            .add_event::<CycleTargetPriority>()
+            .add_event::<SaveReplayEvent>()
+            .add_event::<PlayReplayEvent>()
+            .add_systems(
+                Startup,
+                (
+                    (load_enemy_definitions, setup_enemy_assets).chain(),
+                    setup_tower_assets,
+                    setup_projectile_assets,
+                    setup_status_assets,
+                ),
+            )
+            .add_systems(
+                OnEnter(AppState::ToGame),
+                // a game save is restored over the reset game and the waves of the map
+                (
+                    reset_game,
+                    reset_game_speed,
+                    clear_tower_tiles,
+                    start_replay,
+                    load_waves,
+                    restore_game,
+                    spawn_hud,
+                )
+                    .chain(),
+            )
+            .add_systems(OnEnter(AppState::ToEditor), (reset_game, clear_tower_tiles))
+            .add_systems(OnEnter(AppState::StartMenu), (reset_game, clear_tower_tiles))
+            .add_systems(OnEnter(AppState::Victory), spawn_end_screen)
+            .add_systems(OnEnter(AppState::Defeat), spawn_end_screen)
+            .add_systems(OnExit(AppState::Victory), despawn_end_screen)
+            .add_systems(OnExit(AppState::Defeat), despawn_end_screen)
+            .add_systems(OnEnter(AppState::InGame), apply_game_speed)
+            .add_systems(OnExit(AppState::InGame), (hide_ghost, restore_virtual_time))
+            .add_observer(hover_tile)
+            .add_observer(unhover_tile)
+            .add_observer(place_tower)
+            .add_systems(
+                Update,
+                (
+                    // player input, commands are applied on the next fixed step
+                    (
+                        hud_buttons,
+                        tower_panel_buttons,
+                        replay_buttons,
+                        speed_keys,
+                        save_game,
+                        load_game,
+                        save_replay,
+                        play_replay,
+                    )
+                        .chain(),
+                    (
+                        apply_game_speed.run_if(resource_changed::<GameSpeed>),
+                        update_wave_text,
+                        update_ghost,
+                        update_gold_text,
+                        update_lives_text,
+                        update_speed_text,
+                        update_tower_panel,
+                        update_status_markers,
+                    )
+                        .chain(),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                Update,
+                end_screen_buttons.run_if(in_state(AppState::Victory).or(in_state(AppState::Defeat))),
+            )
+            // the simulation runs in fixed steps of the scaled virtual clock
+            // so its results do not depend on the frame rate or the game speed
+            .add_systems(
+                FixedUpdate,
+                (
+                    // player commands, stamped with the step so a replay applies them on the same one
+                    (
+                        drop_player_commands,
+                        play_commands,
+                        record_commands,
+                        send_next_wave,
+                        build_towers,
+                        sell_towers,
+                        upgrade_towers,
+                        cycle_target_priority,
+                    )
+                        .chain(),
+                    (
+                        tick_waves,
+                        spawn_enemies,
+                        apply_status_effects,
+                        tick_status_effects,
+                        move_enemies,
+                        regenerate_enemies,
+                        heal_enemies,
+                        lose_lives,
+                        tower_attack,
+                        spawn_projectiles,
+                        move_projectiles,
+                        apply_damage,
+                        split_enemies,
+                        // after the spawned, split and killed enemies have been applied
+                        check_waves_cleared,
+                        collect_rewards,
+                        check_game_over,
+                        finish_replay,
+                        advance_tick,
+                    )
+                        .chain(),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame).and(game_undecided)),
+            );
+    }
+}
+
+fn reset_game(mut commands: Commands, scoped: Query<Entity, With<GameScoped>>) {
+    for ent in scoped.iter() {
+        commands.entity(ent).despawn();
+    }
+}
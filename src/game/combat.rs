@@ -0,0 +1,318 @@
+use std::cmp::Ordering;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    damage::{mitigated_damage, DamageType},
+    enemy::{enemy_route, route_share, Enemy, EnemyKind, FlightRoute, Flying, PathProgress, WaveMember},
+    projectile::{FireProjectile, ProjectileSpec, ProjectileStyle},
+    status::{StatusEffect, StatusEffects, StatusKind, Stacking},
+    tower::Tower,
+};
+use crate::tilemap::{tile_center, EnemyPath, TowerType, TILE_SCALE};
+
+/// Combat stats of a tower, `range` in tiles and `fire_rate` in shots per second
+#[derive(Debug, Component, Clone, Copy, PartialEq)]
+pub struct TowerStats {
+    pub range: f32,
+    pub fire_rate: f32,
+    pub damage: f32,
+    pub damage_type: DamageType,
+    pub projectile: ProjectileSpec,
+    /// Whether the tower can target flying enemies
+    pub anti_air: bool,
+    /// Effect applied to every enemy hit
+    pub effect: Option<StatusEffect>,
+}
+
+impl TowerStats {
+    pub fn of(kind: TowerType) -> Self {
+        match kind {
+            TowerType::T1 => TowerStats {
+                range: 2.5,
+                fire_rate: 1.5,
+                damage: 4.0,
+                damage_type: DamageType::Physical,
+                projectile: ProjectileSpec {
+                    style: ProjectileStyle::Homing,
+                    speed: 8.0,
+                    splash_radius: None,
+                },
+                anti_air: false,
+                effect: None,
+            },
+            TowerType::T2 => TowerStats {
+                range: 3.5,
+                fire_rate: 0.6,
+                damage: 12.0,
+                damage_type: DamageType::Fire,
+                projectile: ProjectileSpec {
+                    style: ProjectileStyle::Ballistic,
+                    speed: 4.0,
+                    splash_radius: Some(1.0),
+                },
+                anti_air: false,
+                effect: Some(StatusEffect {
+                    kind: StatusKind::DamageOverTime(DamageType::Fire),
+                    strength: 2.0,
+                    duration: 3.0,
+                    stacking: Stacking::Stack,
+                }),
+            },
+            TowerType::T3 => TowerStats {
+                range: 2.0,
+                fire_rate: 3.0,
+                damage: 2.0,
+                damage_type: DamageType::Magic,
+                projectile: ProjectileSpec {
+                    style: ProjectileStyle::Homing,
+                    speed: 12.0,
+                    splash_radius: None,
+                },
+                anti_air: true,
+                effect: Some(StatusEffect {
+                    kind: StatusKind::Slow,
+                    strength: 0.3,
+                    duration: 1.5,
+                    stacking: Stacking::MaxOf,
+                }),
+            },
+        }
+    }
+}
+
+/// Which enemy in range a tower shoots at
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TargetPriority {
+    /// Furthest along its route towards the Finish tile
+    #[default]
+    First,
+    /// Least far along its route
+    Last,
+    Strongest,
+    Weakest,
+    Closest,
+}
+
+impl TargetPriority {
+    /// Next priority when cycling through them in the HUD
+    pub fn next(&self) -> Self {
+        match self {
+            TargetPriority::First => TargetPriority::Last,
+            TargetPriority::Last => TargetPriority::Strongest,
+            TargetPriority::Strongest => TargetPriority::Weakest,
+            TargetPriority::Weakest => TargetPriority::Closest,
+            TargetPriority::Closest => TargetPriority::First,
+        }
+    }
+}
+
+/// Target acquisition and reload state of a tower
+#[derive(Debug, Component, Clone, Default)]
+pub struct Targeting {
+    pub priority: TargetPriority,
+    pub target: Option<Entity>,
+    /// Seconds until the tower can fire again
+    pub cooldown: f32,
+}
+
+/// Enemy a tower could shoot at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TargetCandidate {
+    pub entity: Entity,
+    pub position: Vec3,
+    /// Share of its route the enemy has covered, walking and flying enemies travel routes of different lengths
+    pub progress: f32,
+    pub health: f32,
+    /// Spawn number of the enemy, see `Enemy::order`
+    pub order: u64,
+}
+
+/// Request to switch a tower to the next target priority
+#[derive(Debug, Clone, Copy, Event)]
+pub struct CycleTargetPriority {
+    pub tower: Entity,
+}
+
+/// Request to damage an enemy, applied by `apply_damage` after armor and resistances
+#[derive(Debug, Clone, Copy, Event)]
+pub struct DamageEnemy {
+    pub enemy: Entity,
+    pub amount: f32,
+    pub damage_type: DamageType,
+}
+
+/// Sent when an enemy's health drops to zero, the enemy is despawned afterwards
+#[derive(Debug, Clone, Copy, Event)]
+pub struct EnemyKilled {
+    pub enemy: Entity,
+    pub kind: EnemyKind,
+    pub position: Vec3,
+    pub progress: f32,
+    pub wave: Option<usize>,
+}
+
+/// Whether a point lies within `range` tiles of `origin`, measured across the ground plane
+pub fn in_range(origin: Vec3, position: Vec3, range: f32) -> bool {
+    origin.xz().distance(position.xz()) <= range * TILE_SCALE
+}
+
+/// Best enemy within range for the given priority, ties go to the enemy spawned first
+pub fn select_target(
+    priority: TargetPriority,
+    origin: Vec3,
+    range: f32,
+    candidates: impl IntoIterator<Item = TargetCandidate>,
+) -> Option<Entity> {
+    let score = |c: &TargetCandidate| match priority {
+        TargetPriority::First => c.progress,
+        TargetPriority::Last => -c.progress,
+        TargetPriority::Strongest => c.health,
+        TargetPriority::Weakest => -c.health,
+        TargetPriority::Closest => -origin.xz().distance(c.position.xz()),
+    };
+
+    candidates
+        .into_iter()
+        .filter(|c| in_range(origin, c.position, range))
+        .max_by(|a, b| {
+            score(a)
+                .partial_cmp(&score(b))
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| b.order.cmp(&a.order))
+        })
+        .map(|c| c.entity)
+}
+
+/// Every tower picks a target each tick and fires a projectile at it when reloaded
+pub(super) fn tower_attack(
+    time: Res<Time>,
+    enemy_path: Res<EnemyPath>,
+    flight_route: Res<FlightRoute>,
+    mut towers: Query<(&Tower, &TowerStats, &mut Targeting, &Transform)>,
+    enemies: Query<(Entity, &Enemy, &PathProgress, &Transform, Has<Flying>)>,
+    mut ev_fire: EventWriter<FireProjectile>,
+) {
+    for (tower, stats, mut targeting, tower_transform) in towers.iter_mut() {
+        targeting.cooldown = (targeting.cooldown - time.delta_secs()).max(0.0);
+
+        let candidates = enemies
+            .iter()
+            .filter(|(_entity, _enemy, _progress, _transform, flying)| !flying || stats.anti_air)
+            .map(|(entity, enemy, progress, transform, flying)| TargetCandidate {
+                entity,
+                position: transform.translation,
+                progress: route_share(enemy_route(flying, &enemy_path, &flight_route), progress.0),
+                health: enemy.health,
+                order: enemy.order,
+            });
+        targeting.target = select_target(targeting.priority, tile_center(tower.loc), stats.range, candidates);
+
+        if let Some(enemy) = targeting.target
+            && targeting.cooldown <= 0.0
+        {
+            ev_fire.write(FireProjectile {
+                origin: tower_transform.translation,
+                target: enemy,
+                damage: stats.damage,
+                damage_type: stats.damage_type,
+                spec: stats.projectile,
+                effect: stats.effect,
+            });
+            targeting.cooldown = 1.0 / stats.fire_rate;
+        }
+    }
+}
+
+pub(super) fn cycle_target_priority(
+    mut ev_cycle_target_priority: EventReader<CycleTargetPriority>,
+    mut towers: Query<&mut Targeting>,
+) {
+    for CycleTargetPriority { tower } in ev_cycle_target_priority.read() {
+        if let Ok(mut targeting) = towers.get_mut(*tower) {
+            targeting.priority = targeting.priority.next();
+        }
+    }
+}
+
+pub(super) fn apply_damage(
+    mut commands: Commands,
+    mut ev_damage: EventReader<DamageEnemy>,
+    mut enemies: Query<(&mut Enemy, &StatusEffects, &Transform, &PathProgress, Option<&WaveMember>)>,
+    mut ev_killed: EventWriter<EnemyKilled>,
+) {
+    for DamageEnemy {
+        enemy: ent,
+        amount,
+        damage_type,
+    } in ev_damage.read()
+    {
+        let Ok((mut enemy, effects, transform, progress, wave)) = enemies.get_mut(*ent) else {
+            continue;
+        };
+        // already killed earlier this frame
+        if enemy.health <= 0.0 {
+            continue;
+        }
+        let armor = effects.reduced_armor(enemy.armor);
+        enemy.health -= mitigated_damage(*amount, *damage_type, armor, &enemy.resistances);
+        if enemy.health <= 0.0 {
+            ev_killed.write(EnemyKilled {
+                enemy: *ent,
+                kind: enemy.kind,
+                position: transform.translation,
+                progress: progress.0,
+                wave: wave.map(|wave| wave.0),
+            });
+            commands.entity(*ent).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates() -> Vec<TargetCandidate> {
+        let candidate = |idx, x: f32, progress, health| TargetCandidate {
+            entity: Entity::from_raw(idx),
+            position: Vec3::new(x * TILE_SCALE, 3.0, 0.0),
+            progress,
+            health,
+            order: idx as u64,
+        };
+        vec![
+            candidate(1, 1.0, 4.0, 10.0),
+            candidate(2, -2.0, 6.0, 5.0),
+            candidate(3, 0.5, 2.0, 30.0),
+            // out of range
+            candidate(4, 9.0, 9.0, 1.0),
+        ]
+    }
+
+    #[test]
+    fn priorities_pick_expected_enemy() {
+        let target = |priority| select_target(priority, Vec3::ZERO, 3.0, candidates()).map(|e| e.index());
+        assert_eq!(target(TargetPriority::First), Some(2));
+        assert_eq!(target(TargetPriority::Last), Some(3));
+        assert_eq!(target(TargetPriority::Strongest), Some(3));
+        assert_eq!(target(TargetPriority::Weakest), Some(2));
+        assert_eq!(target(TargetPriority::Closest), Some(3));
+        assert_eq!(select_target(TargetPriority::First, Vec3::ZERO, 0.1, candidates()), None);
+    }
+
+    #[test]
+    fn ties_go_to_the_oldest_enemy() {
+        let mut tied = candidates();
+        tied[1].progress = tied[0].progress;
+        tied.reverse();
+        let target = select_target(TargetPriority::First, Vec3::ZERO, 3.0, tied.clone());
+        assert_eq!(target.map(|e| e.index()), Some(1));
+
+        // entity ids are reused, the spawn order decides
+        tied[3].order = 9;
+        let target = select_target(TargetPriority::First, Vec3::ZERO, 3.0, tied);
+        assert_eq!(target.map(|e| e.index()), Some(2));
+    }
+}
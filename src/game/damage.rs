@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Share of a hit that always gets through armor and resistances
+pub const MIN_DAMAGE_FRACTION: f32 = 0.2;
+/// Highest share of a hit a resistance can remove
+pub const MAX_RESISTANCE: f32 = 0.9;
+/// Lowest resistance, a weakness of -1.0 doubles the damage taken
+pub const MIN_RESISTANCE: f32 = -1.0;
+
+/// Kind of damage a tower deals, enemies resist each kind separately
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DamageType {
+    #[default]
+    Physical,
+    Magic,
+    Fire,
+    Frost,
+}
+
+impl DamageType {
+    /// Whether armor blocks part of this damage, other kinds are only reduced by resistances
+    pub fn blocked_by_armor(&self) -> bool {
+        matches!(self, DamageType::Physical)
+    }
+}
+
+/// Share of each damage type an enemy ignores, negative values are weaknesses
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Resistances(HashMap<DamageType, f32>);
+
+impl Resistances {
+    pub fn get(&self, damage_type: DamageType) -> f32 {
+        self.0
+            .get(&damage_type)
+            .copied()
+            .unwrap_or_default()
+            .clamp(MIN_RESISTANCE, MAX_RESISTANCE)
+    }
+}
+
+impl<const N: usize> From<[(DamageType, f32); N]> for Resistances {
+    fn from(resistances: [(DamageType, f32); N]) -> Self {
+        Resistances(HashMap::from(resistances))
+    }
+}
+
+/// Damage an enemy takes from a hit, armor ignores a flat amount and resistances a share of the rest
+pub fn mitigated_damage(amount: f32, damage_type: DamageType, armor: f32, resistances: &Resistances) -> f32 {
+    let armored = if damage_type.blocked_by_armor() {
+        amount - armor
+    } else {
+        amount
+    };
+    let resisted = armored * (1.0 - resistances.get(damage_type));
+    resisted.max(amount * MIN_DAMAGE_FRACTION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn armor_never_blocks_a_whole_hit() {
+        let none = Resistances::default();
+        assert_eq!(mitigated_damage(10.0, DamageType::Physical, 3.0, &none), 7.0);
+        assert_eq!(mitigated_damage(2.0, DamageType::Physical, 3.0, &none), 0.4);
+        assert_eq!(mitigated_damage(5.0, DamageType::Physical, 0.0, &none), 5.0);
+        assert_eq!(mitigated_damage(10.0, DamageType::Magic, 3.0, &none), 10.0);
+    }
+
+    #[test]
+    fn resistances_scale_their_damage_type() {
+        let resistances = Resistances::from([(DamageType::Fire, 0.5), (DamageType::Frost, -0.5)]);
+        assert_eq!(mitigated_damage(10.0, DamageType::Fire, 3.0, &resistances), 5.0);
+        assert_eq!(mitigated_damage(10.0, DamageType::Frost, 0.0, &resistances), 15.0);
+        assert_eq!(mitigated_damage(10.0, DamageType::Physical, 2.0, &resistances), 8.0);
+
+        let immune = Resistances::from([(DamageType::Magic, 5.0)]);
+        assert_eq!(immune.get(DamageType::Magic), MAX_RESISTANCE);
+        assert_eq!(mitigated_damage(10.0, DamageType::Magic, 0.0, &immune), 2.0);
+    }
+}
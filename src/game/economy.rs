@@ -0,0 +1,142 @@
+use bevy::prelude::*;
+
+use super::{
+    combat::EnemyKilled,
+    enemy::EnemyDefinitions,
+    tower::{FocusedTower, Tower},
+    wave::WaveCleared,
+};
+use crate::tilemap::{SetTile, TileType, TowerType};
+
+/// Gold to start with when the wave file of a map does not set it
+pub const STARTING_GOLD: u32 = 100;
+/// Percentage of the gold spent on a tower given back when selling it
+pub const SELL_REFUND_PERCENT: u32 = 70;
+
+/// Gold the player has to spend on towers
+#[derive(Debug, Resource, Default, Clone, PartialEq, Eq)]
+pub struct Wallet {
+    gold: u32,
+}
+
+impl Wallet {
+    pub fn new(gold: u32) -> Self {
+        Wallet { gold }
+    }
+
+    pub fn gold(&self) -> u32 {
+        self.gold
+    }
+
+    pub fn can_afford(&self, cost: u32) -> bool {
+        self.gold >= cost
+    }
+
+    /// Pay `cost` if there is enough gold, leaving the wallet untouched otherwise
+    pub fn spend(&mut self, cost: u32) -> bool {
+        if !self.can_afford(cost) {
+            return false;
+        }
+        self.gold -= cost;
+        true
+    }
+
+    pub fn earn(&mut self, amount: u32) {
+        self.gold = self.gold.saturating_add(amount);
+    }
+}
+
+/// Gold spent on a tower so far, used to work out its refund
+#[derive(Debug, Component, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TowerValue(pub u32);
+
+/// Request to sell a tower, turning its tile back into a Free tile
+#[derive(Debug, Clone, Copy, Event)]
+pub struct SellTower {
+    pub tower: Entity,
+}
+
+pub fn tower_cost(kind: TowerType) -> u32 {
+    match kind {
+        TowerType::T1 => 50,
+        TowerType::T2 => 120,
+        TowerType::T3 => 80,
+    }
+}
+
+/// Bonus for clearing a wave, later waves pay more
+pub fn wave_clear_reward(wave: usize) -> u32 {
+    20 + 5 * wave as u32
+}
+
+/// Gold given back for a tower, rounded down
+pub fn sell_refund(value: TowerValue) -> u32 {
+    value.0 * SELL_REFUND_PERCENT / 100
+}
+
+/// Kill rewards come from the enemy definitions
+pub(super) fn collect_rewards(
+    mut wallet: ResMut<Wallet>,
+    definitions: Res<EnemyDefinitions>,
+    mut ev_killed: EventReader<EnemyKilled>,
+    mut ev_wave_cleared: EventReader<WaveCleared>,
+) {
+    for killed in ev_killed.read() {
+        wallet.earn(definitions.get(killed.kind).reward);
+    }
+    for cleared in ev_wave_cleared.read() {
+        wallet.earn(wave_clear_reward(cleared.wave));
+    }
+}
+
+pub(super) fn sell_towers(
+    mut commands: Commands,
+    mut ev_sell_tower: EventReader<SellTower>,
+    mut ev_set_tile: EventWriter<SetTile>,
+    mut wallet: ResMut<Wallet>,
+    mut focused: ResMut<FocusedTower>,
+    towers: Query<(&Tower, &TowerValue)>,
+) {
+    for SellTower { tower: ent } in ev_sell_tower.read() {
+        // sold twice on the same frame
+        let Ok((tower, value)) = towers.get(*ent) else {
+            continue;
+        };
+        let refund = sell_refund(*value);
+        info!("Sold {:?} at {} for {refund} gold", tower.kind, tower.loc);
+        wallet.earn(refund);
+        commands.entity(*ent).despawn();
+        if focused.0 == Some(*ent) {
+            focused.0 = None;
+        }
+        ev_set_tile.write(SetTile {
+            loc: tower.loc,
+            tile: TileType::Free,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wallet_only_spends_what_it_has() {
+        let mut wallet = Wallet::new(60);
+        assert!(wallet.spend(tower_cost(TowerType::T1)));
+        assert_eq!(wallet.gold(), 10);
+        assert!(!wallet.can_afford(tower_cost(TowerType::T1)));
+        assert!(!wallet.spend(tower_cost(TowerType::T1)));
+        assert_eq!(wallet.gold(), 10);
+
+        wallet.earn(wave_clear_reward(1));
+        assert_eq!(wallet.gold(), 35);
+    }
+
+    #[test]
+    fn refunds_round_down() {
+        assert_eq!(sell_refund(TowerValue(50)), 35);
+        assert_eq!(sell_refund(TowerValue(85)), 59);
+        assert_eq!(sell_refund(TowerValue(0)), 0);
+    }
+}
@@ -0,0 +1,516 @@
+use std::{collections::HashMap, fmt, fs, io, iter, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{combat::EnemyKilled, damage::Resistances, status::StatusEffects, GameScoped};
+use crate::tilemap::{tile_center, EnemyPath, TILE_SCALE};
+
+/// Enemy definitions read at startup, tune them without rebuilding the game
+pub const ENEMY_FILE: &str = "assets/enemies.txt";
+/// Definitions built into the game, used when `ENEMY_FILE` is missing or broken
+const DEFAULT_ENEMIES: &str = include_str!("../../assets/enemies.txt");
+/// Height of the enemy centre above the tiles, scaled with the size of the enemy
+pub const ENEMY_HEIGHT: f32 = 0.3 * TILE_SCALE;
+/// Height flying enemies travel at, clear of the tiles and most towers
+pub const FLIGHT_ALTITUDE: f32 = 2.0 * TILE_SCALE;
+
+/// Kinds of enemy a wave can send, their stats and abilities are defined in `ENEMY_FILE`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EnemyKind {
+    #[default]
+    Basic,
+    Fast,
+    Tough,
+    Armored,
+    Regenerator,
+    Healer,
+    Splitter,
+    Boss,
+    Flyer,
+}
+
+/// Health given every second to other enemies within `radius` tiles
+#[derive(Debug, Component, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HealAura {
+    pub radius: f32,
+    pub amount: f32,
+}
+
+/// Enemies spawned where the enemy dies
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Split {
+    pub kind: EnemyKind,
+    pub count: u32,
+}
+
+/// Health regenerated every second
+#[derive(Debug, Component, Clone, Copy, PartialEq)]
+pub struct Regenerates(pub f32);
+
+/// Enemy following the FlightRoute instead of the EnemyPath, only anti-air towers can target it
+#[derive(Debug, Component, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flying;
+
+/// Stats and abilities of one kind of enemy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnemyDefinition {
+    pub health: f32,
+    /// Tiles per second
+    pub speed: f32,
+    /// Physical damage ignored from every hit
+    #[serde(default)]
+    pub armor: f32,
+    /// Share of each damage type ignored, see `mitigated_damage`
+    #[serde(default)]
+    pub resistances: Resistances,
+    #[serde(default)]
+    pub regen: f32,
+    #[serde(default)]
+    pub heal: Option<HealAura>,
+    #[serde(default)]
+    pub split: Option<Split>,
+    #[serde(default)]
+    pub flying: bool,
+    /// Scale of the enemy mesh
+    #[serde(default = "default_size")]
+    pub size: f32,
+    /// sRGB color of the enemy mesh
+    pub color: [f32; 3],
+    /// Lives lost when the enemy reaches the Finish tile
+    #[serde(default = "default_lives_cost")]
+    pub lives_cost: u32,
+    /// Gold for killing the enemy
+    pub reward: u32,
+}
+
+fn default_size() -> f32 {
+    1.0
+}
+
+fn default_lives_cost() -> u32 {
+    1
+}
+
+#[derive(Debug)]
+pub enum EnemyFileError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for EnemyFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnemyFileError::Io(e) => write!(f, "unable to read enemy file: {e}"),
+            EnemyFileError::Parse(e) => write!(f, "unable to parse enemy file: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for EnemyFileError {}
+
+/// Definition of every kind of enemy
+#[derive(Debug, Resource, Clone, PartialEq)]
+pub struct EnemyDefinitions(HashMap<EnemyKind, EnemyDefinition>);
+
+impl Default for EnemyDefinitions {
+    fn default() -> Self {
+        let definitions = serde_json::from_str(DEFAULT_ENEMIES).expect("built in enemy definitions should parse");
+        EnemyDefinitions(definitions)
+    }
+}
+
+impl EnemyDefinitions {
+    /// Read definitions from a file, kinds left out of the file keep their built in definition
+    pub fn read(path: &Path) -> Result<Self, EnemyFileError> {
+        let contents = fs::read_to_string(path).map_err(EnemyFileError::Io)?;
+        let overrides: HashMap<EnemyKind, EnemyDefinition> =
+            serde_json::from_str(&contents).map_err(EnemyFileError::Parse)?;
+        let mut definitions = EnemyDefinitions::default();
+        definitions.0.extend(overrides);
+        Ok(definitions)
+    }
+
+    pub fn get(&self, kind: EnemyKind) -> &EnemyDefinition {
+        &self.0[&kind]
+    }
+}
+
+/// Enemy walking the EnemyPath, `speed` in tiles per second
+#[derive(Debug, Component, Clone, PartialEq)]
+pub struct Enemy {
+    pub kind: EnemyKind,
+    pub health: f32,
+    pub max_health: f32,
+    pub speed: f32,
+    pub armor: f32,
+    pub resistances: Resistances,
+    /// Number of enemies spawned before this one in the game, stable across runs unlike entity ids
+    pub order: u64,
+}
+
+impl Enemy {
+    pub fn new(kind: EnemyKind, definition: &EnemyDefinition, order: u64) -> Self {
+        Enemy {
+            kind,
+            order,
+            health: definition.health,
+            max_health: definition.health,
+            speed: definition.speed,
+            armor: definition.armor,
+            resistances: definition.resistances.clone(),
+        }
+    }
+
+    pub fn heal(&mut self, amount: f32) {
+        self.health = (self.health + amount).min(self.max_health);
+    }
+}
+
+/// Enemies spawned since the game started, numbers new enemies
+#[derive(Debug, Resource, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EnemyCounter(pub u64);
+
+/// Wave an enemy was sent with, used to tell when a wave has been cleared
+#[derive(Debug, Component, Clone, Copy, PartialEq, Eq)]
+pub struct WaveMember(pub usize);
+
+/// Route of flying enemies, straight from the Start tile over the waypoints of the map to the Finish tile
+#[derive(Debug, Resource, Default, Clone, PartialEq)]
+pub struct FlightRoute(pub Vec<IVec2>);
+
+impl FlightRoute {
+    pub fn new(enemy_path: &EnemyPath, waypoints: &[IVec2]) -> Self {
+        match (enemy_path.start(), enemy_path.finish()) {
+            (Some(start), Some(finish)) => FlightRoute(
+                iter::once(start)
+                    .chain(waypoints.iter().copied())
+                    .chain(iter::once(finish))
+                    .collect(),
+            ),
+            _ => FlightRoute::default(),
+        }
+    }
+}
+
+/// Route an enemy travels along
+pub fn enemy_route<'a>(flying: bool, enemy_path: &'a EnemyPath, flight_route: &'a FlightRoute) -> &'a [IVec2] {
+    if flying {
+        &flight_route.0
+    } else {
+        enemy_path.tiles()
+    }
+}
+
+/// Distance travelled along its route in tiles, 0 on the Start tile
+#[derive(Debug, Component, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub struct PathProgress(pub f32);
+
+/// Request to spawn an enemy on the Start tile, `wave` is `None` for enemies sent outside of a wave
+#[derive(Debug, Clone, Copy, Default, Event)]
+pub struct SpawnEnemy {
+    pub kind: EnemyKind,
+    pub wave: Option<usize>,
+}
+
+/// Sent when an enemy walks onto the Finish tile, the enemy is despawned afterwards
+#[derive(Debug, Clone, Copy, Event)]
+pub struct EnemyReachedFinish {
+    pub enemy: Entity,
+    pub kind: EnemyKind,
+}
+
+/// Mesh shared by every enemy and the material of each kind
+#[derive(Debug, Resource)]
+pub struct EnemyAssets {
+    mesh: Handle<Mesh>,
+    materials: HashMap<EnemyKind, Handle<StandardMaterial>>,
+}
+
+/// Read the enemy definitions, falling back to the built in ones
+pub(super) fn load_enemy_definitions(mut commands: Commands) {
+    let definitions = match EnemyDefinitions::read(Path::new(ENEMY_FILE)) {
+        Ok(definitions) => definitions,
+        Err(e) => {
+            warn!("{e}, using built in enemy definitions");
+            EnemyDefinitions::default()
+        }
+    };
+    commands.insert_resource(definitions);
+}
+
+pub(super) fn setup_enemy_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    definitions: Res<EnemyDefinitions>,
+) {
+    commands.insert_resource(EnemyAssets {
+        mesh: meshes.add(Sphere::new(0.25 * TILE_SCALE)),
+        materials: definitions
+            .0
+            .iter()
+            .map(|(kind, definition)| {
+                let [r, g, b] = definition.color;
+                (*kind, materials.add(Color::srgb(r, g, b)))
+            })
+            .collect(),
+    });
+}
+
+/// Position `distance` tiles along an ordered route, moving in a straight line between tile centres
+/// Route points need not be neighbours, the EnemyPath has one tile between them and the FlightRoute more
+pub fn path_position(path: &[IVec2], distance: f32) -> Option<Vec3> {
+    let (first, rest) = path.split_first()?;
+    let mut remaining = distance.max(0.0);
+    let mut from = *first;
+    for to in rest {
+        let length = from.as_vec2().distance(to.as_vec2());
+        if remaining <= length {
+            let t = if length > 0.0 { remaining / length } else { 0.0 };
+            return Some(tile_center(from).lerp(tile_center(*to), t));
+        }
+        remaining -= length;
+        from = *to;
+    }
+    Some(tile_center(from))
+}
+
+/// Length of a route in tiles
+pub fn path_length(path: &[IVec2]) -> f32 {
+    path.windows(2).map(|pair| pair[0].as_vec2().distance(pair[1].as_vec2())).sum()
+}
+
+/// Share of a route covered `progress` tiles along it, lets progress along different routes be compared
+pub fn route_share(route: &[IVec2], progress: f32) -> f32 {
+    let length = path_length(route);
+    if length > 0.0 { progress / length } else { 0.0 }
+}
+
+/// Height of an enemy centre above the ground
+fn enemy_height(flying: bool, size: f32) -> f32 {
+    if flying { FLIGHT_ALTITUDE } else { ENEMY_HEIGHT * size }
+}
+
+/// Spawn an enemy `progress` tiles along its route
+fn spawn_enemy(
+    commands: &mut Commands,
+    assets: &EnemyAssets,
+    definitions: &EnemyDefinitions,
+    counter: &mut EnemyCounter,
+    route: &[IVec2],
+    SpawnEnemy { kind, wave }: SpawnEnemy,
+    progress: f32,
+) {
+    let definition = definitions.get(kind);
+    let Some(pos) = path_position(route, progress) else {
+        return;
+    };
+    let order = counter.0;
+    counter.0 += 1;
+    let mut enemy = commands.spawn((
+        Enemy::new(kind, definition, order),
+        PathProgress(progress),
+        StatusEffects::default(),
+        Mesh3d(assets.mesh.clone()),
+        MeshMaterial3d(assets.materials[&kind].clone()),
+        Transform::from_translation(pos + Vec3::Y * enemy_height(definition.flying, definition.size))
+            .with_scale(Vec3::splat(definition.size)),
+        GameScoped,
+    ));
+    if let Some(wave) = wave {
+        enemy.insert(WaveMember(wave));
+    }
+    if definition.flying {
+        enemy.insert(Flying);
+    }
+    if definition.regen > 0.0 {
+        enemy.insert(Regenerates(definition.regen));
+    }
+    if let Some(heal) = definition.heal {
+        enemy.insert(heal);
+    }
+}
+
+pub(super) fn spawn_enemies(
+    mut commands: Commands,
+    mut ev_spawn_enemy: EventReader<SpawnEnemy>,
+    enemy_path: Res<EnemyPath>,
+    flight_route: Res<FlightRoute>,
+    assets: Res<EnemyAssets>,
+    definitions: Res<EnemyDefinitions>,
+    mut counter: ResMut<EnemyCounter>,
+) {
+    for spawn in ev_spawn_enemy.read() {
+        // enemies need somewhere to walk to
+        if enemy_path.tiles().len() < 2 {
+            warn!("Unable to spawn enemy, the map has no enemy path");
+            continue;
+        }
+        let flying = definitions.get(spawn.kind).flying;
+        spawn_enemy(
+            &mut commands,
+            &assets,
+            &definitions,
+            &mut counter,
+            enemy_route(flying, &enemy_path, &flight_route),
+            *spawn,
+            0.0,
+        );
+    }
+}
+
+/// Move every enemy along its route, despawning those reaching the Finish tile
+pub(super) fn move_enemies(
+    mut commands: Commands,
+    time: Res<Time>,
+    enemy_path: Res<EnemyPath>,
+    flight_route: Res<FlightRoute>,
+    mut enemies: Query<(Entity, &Enemy, &mut PathProgress, &mut Transform, Has<Flying>)>,
+    effects: Query<&StatusEffects>,
+    mut ev_reached_finish: EventWriter<EnemyReachedFinish>,
+) {
+    let walk_length = path_length(enemy_path.tiles());
+    let flight_length = path_length(&flight_route.0);
+
+    for (ent, enemy, mut progress, mut transform, flying) in enemies.iter_mut() {
+        let finish = if flying { flight_length } else { walk_length };
+        let speed_multiplier = effects.get(ent).map_or(1.0, StatusEffects::speed_multiplier);
+        progress.0 += enemy.speed * speed_multiplier * time.delta_secs();
+        if progress.0 >= finish {
+            ev_reached_finish.write(EnemyReachedFinish {
+                enemy: ent,
+                kind: enemy.kind,
+            });
+            commands.entity(ent).despawn();
+            continue;
+        }
+        if let Some(pos) = path_position(enemy_route(flying, &enemy_path, &flight_route), progress.0) {
+            transform.translation = pos + Vec3::Y * enemy_height(flying, transform.scale.y);
+        }
+    }
+}
+
+pub(super) fn regenerate_enemies(time: Res<Time>, mut enemies: Query<(&mut Enemy, &Regenerates)>) {
+    for (mut enemy, regen) in enemies.iter_mut() {
+        enemy.heal(regen.0 * time.delta_secs());
+    }
+}
+
+/// Healers mend every other enemy around them
+pub(super) fn heal_enemies(
+    time: Res<Time>,
+    healers: Query<(Entity, &HealAura, &Transform)>,
+    mut enemies: Query<(Entity, &mut Enemy, &Transform)>,
+) {
+    for (healer, aura, healer_transform) in healers.iter() {
+        for (ent, mut enemy, transform) in enemies.iter_mut() {
+            let distance = healer_transform.translation.xz().distance(transform.translation.xz());
+            if ent != healer && distance <= aura.radius * TILE_SCALE {
+                enemy.heal(aura.amount * time.delta_secs());
+            }
+        }
+    }
+}
+
+/// Splitters leave their children where they died, as part of the same wave
+pub(super) fn split_enemies(
+    mut commands: Commands,
+    mut ev_killed: EventReader<EnemyKilled>,
+    enemy_path: Res<EnemyPath>,
+    flight_route: Res<FlightRoute>,
+    assets: Res<EnemyAssets>,
+    definitions: Res<EnemyDefinitions>,
+    mut counter: ResMut<EnemyCounter>,
+) {
+    for killed in ev_killed.read() {
+        let Some(split) = definitions.get(killed.kind).split else {
+            continue;
+        };
+        // children flying or walking unlike the splitter start as far along their own route
+        let splitter_route = enemy_route(definitions.get(killed.kind).flying, &enemy_path, &flight_route);
+        let route = enemy_route(definitions.get(split.kind).flying, &enemy_path, &flight_route);
+        let died_at = route_share(splitter_route, killed.progress) * path_length(route);
+        for idx in 0..split.count {
+            // spread the children out behind where the splitter died
+            let progress = (died_at - 0.2 * idx as f32).max(0.0);
+            spawn_enemy(
+                &mut commands,
+                &assets,
+                &definitions,
+                &mut counter,
+                route,
+                SpawnEnemy {
+                    kind: split.kind,
+                    wave: killed.wave,
+                },
+                progress,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_position_moves_between_tile_centres() {
+        let path = [IVec2::new(0, 0), IVec2::new(1, 0), IVec2::new(1, 1)];
+        assert_eq!(path_position(&path, 0.0), Some(tile_center(path[0])));
+        assert_eq!(path_position(&path, 0.5), Some(Vec3::new(0.5 * TILE_SCALE, 0.0, 0.0)));
+        assert_eq!(
+            path_position(&path, 1.25),
+            Some(Vec3::new(TILE_SCALE, 0.0, 0.25 * TILE_SCALE))
+        );
+        // clamped to the Finish tile
+        assert_eq!(path_position(&path, 7.0), Some(tile_center(path[2])));
+        assert_eq!(path_position(&[], 1.0), None);
+        assert_eq!(path_length(&path), 2.0);
+    }
+
+    #[test]
+    fn flight_route_flies_straight_between_waypoints() {
+        let enemy_path = EnemyPath(Some(vec![IVec2::new(0, 0), IVec2::new(0, 1), IVec2::new(0, 2), IVec2::new(6, 8)]));
+        let route = FlightRoute::new(&enemy_path, &[IVec2::new(6, 0)]);
+        assert_eq!(route.0, vec![IVec2::new(0, 0), IVec2::new(6, 0), IVec2::new(6, 8)]);
+        assert_eq!(path_length(&route.0), 14.0);
+        assert_eq!(path_position(&route.0, 3.0), Some(tile_center(IVec2::new(3, 0))));
+        assert_eq!(path_position(&route.0, 10.0), Some(tile_center(IVec2::new(6, 4))));
+        assert_eq!(route_share(&route.0, 7.0), 0.5);
+        assert_eq!(route_share(&[], 7.0), 0.0);
+        assert_eq!(FlightRoute::new(&EnemyPath(None), &[]), FlightRoute::default());
+    }
+
+    #[test]
+    fn every_kind_has_a_definition() {
+        let definitions = EnemyDefinitions::default();
+        let kinds = [
+            EnemyKind::Basic,
+            EnemyKind::Fast,
+            EnemyKind::Tough,
+            EnemyKind::Armored,
+            EnemyKind::Regenerator,
+            EnemyKind::Healer,
+            EnemyKind::Splitter,
+            EnemyKind::Boss,
+            EnemyKind::Flyer,
+        ];
+        for kind in kinds {
+            assert!(definitions.get(kind).health > 0.0, "{kind:?}");
+        }
+        assert_eq!(definitions.get(EnemyKind::Splitter).split.map(|split| split.kind), Some(EnemyKind::Fast));
+        assert!(definitions.get(EnemyKind::Boss).lives_cost > 1);
+        assert_eq!(EnemyDefinitions::read(Path::new(ENEMY_FILE)).expect("enemy file should parse"), definitions);
+    }
+
+    #[test]
+    fn healing_stops_at_max_health() {
+        let mut enemy = Enemy::new(EnemyKind::Basic, EnemyDefinitions::default().get(EnemyKind::Basic), 1);
+        enemy.health = 4.0;
+        enemy.heal(3.0);
+        assert_eq!(enemy.health, 7.0);
+        enemy.heal(30.0);
+        assert_eq!(enemy.health, enemy.max_health);
+    }
+}
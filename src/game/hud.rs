@@ -0,0 +1,306 @@
+use bevy::prelude::*;
+
+use super::{
+    combat::{CycleTargetPriority, Targeting, TowerStats},
+    economy::{sell_refund, tower_cost, SellTower, TowerValue, Wallet},
+    outcome::Lives,
+    replay::{ActiveReplay, PlayReplayEvent, SaveReplayEvent},
+    save::{LoadGameEvent, SaveGameEvent},
+    speed::{GameSpeed, SPEEDS},
+    tower::{FocusedTower, SelectedTower, Tower},
+    upgrade::{upgrade_tree, TowerLevel, UpgradeTower},
+    wave::{SendNextWave, WaveScheduler},
+    GameScoped,
+};
+use crate::{
+    tilemap::TowerType,
+    ui::{button, ButtonType, MenuType, HOVERED_BUTTON, NORMAL_BUTTON, PRESSED_BUTTON},
+};
+
+#[derive(Debug, Component)]
+pub(super) struct WaveText;
+
+#[derive(Debug, Component)]
+pub(super) struct GoldText;
+
+#[derive(Debug, Component)]
+pub(super) struct LivesText;
+
+#[derive(Debug, Component)]
+pub(super) struct TowerPanelText;
+
+#[derive(Debug, Component)]
+pub(super) struct SpeedText;
+
+/// In game overlay with the wave counter, gold, lives, the Next Wave button, speed controls and the tower palette
+pub(super) fn spawn_hud(mut commands: Commands) {
+    let tower_button = |kind: TowerType| {
+        button(format!("{kind:?} ({})", tower_cost(kind)), ButtonType::Menu(MenuType::Tower(kind)))
+    };
+    let speed_button = |multiplier: u32| {
+        button(format!("{multiplier}x"), ButtonType::Menu(MenuType::GameSpeed(multiplier)))
+    };
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(10.0),
+            top: Val::Px(10.0),
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(6.0),
+            ..default()
+        },
+        GameScoped,
+        children![
+            (Text::new(""), WaveText),
+            (Text::new(""), GoldText),
+            (Text::new(""), LivesText),
+            button("Next Wave", ButtonType::Menu(MenuType::NextWave)),
+            (
+                Node {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(6.0),
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                children![
+                    (Text::new(""), SpeedText),
+                    button("Pause", ButtonType::Menu(MenuType::PauseGame)),
+                    speed_button(SPEEDS[0]),
+                    speed_button(SPEEDS[1]),
+                    speed_button(SPEEDS[2]),
+                ]
+            ),
+            (
+                Node {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(6.0),
+                    ..default()
+                },
+                children![
+                    tower_button(TowerType::T1),
+                    tower_button(TowerType::T2),
+                    tower_button(TowerType::T3),
+                ]
+            ),
+            // focused tower
+            (Text::new(""), TextFont::from_font_size(14.0), TowerPanelText),
+            (
+                Node {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(6.0),
+                    ..default()
+                },
+                children![
+                    button("Target", ButtonType::Menu(MenuType::TargetPriority)),
+                    button("Sell", ButtonType::Menu(MenuType::SellTower)),
+                ]
+            ),
+            (
+                Node {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(6.0),
+                    ..default()
+                },
+                children![
+                    button("Upgrade 1", ButtonType::Menu(MenuType::Upgrade(0))),
+                    button("Upgrade 2", ButtonType::Menu(MenuType::Upgrade(1))),
+                ]
+            ),
+            (
+                Node {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(6.0),
+                    ..default()
+                },
+                children![
+                    button("Save Game", ButtonType::Menu(MenuType::SaveGame)),
+                    button("Load Game", ButtonType::Menu(MenuType::LoadGame)),
+                ]
+            ),
+            (
+                Node {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(6.0),
+                    ..default()
+                },
+                children![
+                    button("Save Replay", ButtonType::Menu(MenuType::SaveReplay)),
+                    button("Play Replay", ButtonType::Menu(MenuType::PlayReplay)),
+                ]
+            ),
+        ],
+    ));
+}
+
+pub(super) fn hud_buttons(
+    mut buttons: Query<(&ButtonType, &Interaction, &mut BackgroundColor), Changed<Interaction>>,
+    mut ev_send_next_wave: EventWriter<SendNextWave>,
+    mut selected_tower: ResMut<SelectedTower>,
+    mut ev_save_game: EventWriter<SaveGameEvent>,
+    mut ev_load_game: EventWriter<LoadGameEvent>,
+    mut speed: ResMut<GameSpeed>,
+) {
+    for (button_type, interaction, mut color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                match button_type {
+                    ButtonType::Menu(MenuType::NextWave) => {
+                        ev_send_next_wave.write(SendNextWave);
+                    }
+                    ButtonType::Menu(MenuType::Tower(kind)) => selected_tower.0 = Some(*kind),
+                    ButtonType::Menu(MenuType::SaveGame) => {
+                        ev_save_game.write(SaveGameEvent);
+                    }
+                    ButtonType::Menu(MenuType::LoadGame) => {
+                        ev_load_game.write(LoadGameEvent);
+                    }
+                    ButtonType::Menu(MenuType::PauseGame) => speed.toggle_pause(),
+                    ButtonType::Menu(MenuType::GameSpeed(multiplier)) => speed.set(*multiplier),
+                    _ => (),
+                }
+            }
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    }
+}
+
+/// Buttons acting on the focused tower, their colors are handled by `hud_buttons`
+pub(super) fn tower_panel_buttons(
+    buttons: Query<(&ButtonType, &Interaction), Changed<Interaction>>,
+    focused: Res<FocusedTower>,
+    mut ev_cycle_target_priority: EventWriter<CycleTargetPriority>,
+    mut ev_sell_tower: EventWriter<SellTower>,
+    mut ev_upgrade_tower: EventWriter<UpgradeTower>,
+) {
+    let Some(tower) = focused.0 else {
+        return;
+    };
+    for (button_type, interaction) in buttons.iter() {
+        if interaction != &Interaction::Pressed {
+            continue;
+        }
+        match button_type {
+            ButtonType::Menu(MenuType::TargetPriority) => {
+                ev_cycle_target_priority.write(CycleTargetPriority { tower });
+            }
+            ButtonType::Menu(MenuType::SellTower) => {
+                ev_sell_tower.write(SellTower { tower });
+            }
+            ButtonType::Menu(MenuType::Upgrade(choice)) => {
+                ev_upgrade_tower.write(UpgradeTower { tower, choice: *choice });
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Buttons recording and playing back replays, their colors are handled by `hud_buttons`
+pub(super) fn replay_buttons(
+    buttons: Query<(&ButtonType, &Interaction), Changed<Interaction>>,
+    mut ev_save_replay: EventWriter<SaveReplayEvent>,
+    mut ev_play_replay: EventWriter<PlayReplayEvent>,
+) {
+    for (button_type, interaction) in buttons.iter() {
+        if interaction != &Interaction::Pressed {
+            continue;
+        }
+        match button_type {
+            ButtonType::Menu(MenuType::SaveReplay) => {
+                ev_save_replay.write(SaveReplayEvent);
+            }
+            ButtonType::Menu(MenuType::PlayReplay) => {
+                ev_play_replay.write(PlayReplayEvent);
+            }
+            _ => (),
+        }
+    }
+}
+
+pub(super) fn update_wave_text(
+    scheduler: Res<WaveScheduler>,
+    mut text_query: Query<(&mut Text, Ref<WaveText>)>,
+) {
+    for (mut text, wave_text) in text_query.iter_mut() {
+        if scheduler.is_changed() || wave_text.is_added() {
+            text.0 = format!("Wave {}/{}", scheduler.sent(), scheduler.total());
+        }
+    }
+}
+
+pub(super) fn update_gold_text(wallet: Res<Wallet>, mut text_query: Query<(&mut Text, Ref<GoldText>)>) {
+    for (mut text, gold_text) in text_query.iter_mut() {
+        if wallet.is_changed() || gold_text.is_added() {
+            text.0 = format!("Gold: {}", wallet.gold());
+        }
+    }
+}
+
+pub(super) fn update_lives_text(lives: Res<Lives>, mut text_query: Query<(&mut Text, Ref<LivesText>)>) {
+    for (mut text, lives_text) in text_query.iter_mut() {
+        if lives.is_changed() || lives_text.is_added() {
+            text.0 = format!("Lives: {}", lives.0);
+        }
+    }
+}
+
+/// Game speed, marked while a replay is played back
+pub(super) fn update_speed_text(
+    speed: Res<GameSpeed>,
+    replay: Res<ActiveReplay>,
+    mut text_query: Query<(&mut Text, Ref<SpeedText>)>,
+) {
+    for (mut text, speed_text) in text_query.iter_mut() {
+        if speed.is_changed() || speed_text.is_added() {
+            let playing = if replay.is_playing() { ", replay" } else { "" };
+            text.0 = format!("{}{playing}", speed.label());
+        }
+    }
+}
+
+/// Describe the focused tower along with its upgrades, empty when no tower is focused
+pub(super) fn update_tower_panel(
+    focused: Res<FocusedTower>,
+    towers: Query<(&Tower, Ref<Targeting>, Ref<TowerLevel>, &TowerValue)>,
+    stats: Query<&TowerStats>,
+    mut text_query: Query<&mut Text, With<TowerPanelText>>,
+) {
+    let tower = focused.0.and_then(|ent| towers.get(ent).ok());
+    let tower_changed = tower
+        .as_ref()
+        .is_some_and(|(_tower, targeting, level, _value)| targeting.is_changed() || level.is_changed());
+    if !focused.is_changed() && !tower_changed {
+        return;
+    }
+    for mut text in text_query.iter_mut() {
+        text.0 = match &tower {
+            Some((tower, targeting, level, value)) => {
+                let tree = upgrade_tree(tower.kind);
+                let upgrades = level
+                    .next(&tree)
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, (_next, step))| format!("\nUpgrade {}: {} ({} gold)", idx + 1, step.name, step.cost))
+                    .collect::<String>();
+                let damage = match focused.0.and_then(|ent| stats.get(ent).ok()) {
+                    Some(stats) => {
+                        let anti_air = if stats.anti_air { ", anti-air" } else { "" };
+                        let effect = stats.effect.map(|effect| format!(", {:?}", effect.kind)).unwrap_or_default();
+                        format!("\n{:.1} {:?} damage{anti_air}{effect}", stats.damage, stats.damage_type)
+                    }
+                    None => String::new(),
+                };
+                format!(
+                    "{:?} {} at {}{damage}\nTarget: {:?}\nSells for {} gold{upgrades}",
+                    tower.kind,
+                    level.name(&tree),
+                    tower.loc,
+                    targeting.priority,
+                    sell_refund(**value)
+                )
+            }
+            None => String::new(),
+        };
+    }
+}
@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+
+use super::{
+    enemy::{EnemyDefinitions, EnemyReachedFinish},
+    wave::AllWavesCleared,
+};
+use crate::{
+    ui::{button, ButtonType, MenuType, HOVERED_BUTTON, NORMAL_BUTTON, PRESSED_BUTTON},
+    AppState,
+};
+
+/// Lives to start with when the wave file of a map does not set them
+pub const STARTING_LIVES: u32 = 20;
+
+/// Lives left, the game is lost when they run out
+#[derive(Debug, Resource, Clone, Copy, PartialEq, Eq)]
+pub struct Lives(pub u32);
+
+impl Default for Lives {
+    fn default() -> Self {
+        Lives(STARTING_LIVES)
+    }
+}
+
+/// Victory or Defeat screen shown over the finished game
+#[derive(Debug, Component)]
+pub(super) struct EndScreen;
+
+/// Every enemy reaching the Finish tile costs lives, bosses cost more than one
+pub(super) fn lose_lives(
+    mut lives: ResMut<Lives>,
+    definitions: Res<EnemyDefinitions>,
+    mut ev_reached_finish: EventReader<EnemyReachedFinish>,
+) {
+    for reached in ev_reached_finish.read() {
+        lives.0 = lives.0.saturating_sub(definitions.get(reached.kind).lives_cost);
+    }
+}
+
+/// Running out of lives loses the game even if the final wave was cleared on the same frame
+pub(super) fn check_game_over(
+    lives: Res<Lives>,
+    mut ev_all_cleared: EventReader<AllWavesCleared>,
+    mut app_state: ResMut<NextState<AppState>>,
+) {
+    if lives.0 == 0 {
+        info!("Out of lives, game lost");
+        app_state.set(AppState::Defeat);
+    } else if ev_all_cleared.read().last().is_some() {
+        info!("Final wave cleared, game won");
+        app_state.set(AppState::Victory);
+    }
+}
+
+/// Run condition of the simulation, it stops once the outcome is decided
+/// so a later fixed step on the same frame cannot turn a Victory into a Defeat
+pub(super) fn game_undecided(app_state: Res<NextState<AppState>>) -> bool {
+    !matches!(*app_state, NextState::Pending(AppState::Victory | AppState::Defeat))
+}
+
+pub(super) fn spawn_end_screen(mut commands: Commands, app_state: Res<State<AppState>>, lives: Res<Lives>) {
+    let title = match app_state.get() {
+        AppState::Victory => format!("Victory!\n{} lives left", lives.0),
+        _ => "Defeat".to_string(),
+    };
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(10.0),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        EndScreen,
+        children![
+            (Text::new(title), TextFont::from_font_size(40.0), TextLayout::new_with_justify(JustifyText::Center)),
+            (
+                Node {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(10.0),
+                    ..default()
+                },
+                children![
+                    button("Retry", ButtonType::Menu(MenuType::Retry)),
+                    button("Main Menu", ButtonType::Menu(MenuType::MainMenu)),
+                    button("Edit Map", ButtonType::Menu(MenuType::LevelEdit)),
+                ]
+            ),
+        ],
+    ));
+}
+
+pub(super) fn despawn_end_screen(mut commands: Commands, screens: Query<Entity, With<EndScreen>>) {
+    for ent in screens.iter() {
+        commands.entity(ent).despawn();
+    }
+}
+
+pub(super) fn end_screen_buttons(
+    mut buttons: Query<(&ButtonType, &Interaction, &mut BackgroundColor), Changed<Interaction>>,
+    mut app_state: ResMut<NextState<AppState>>,
+) {
+    for (button_type, interaction, mut color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                match button_type {
+                    ButtonType::Menu(MenuType::Retry) => app_state.set(AppState::ToGame),
+                    ButtonType::Menu(MenuType::MainMenu) => app_state.set(AppState::StartMenu),
+                    ButtonType::Menu(MenuType::LevelEdit) => app_state.set(AppState::ToEditor),
+                    _ => (),
+                }
+            }
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decided_games_stay_decided() {
+        let mut world = World::new();
+        world.insert_resource(Lives(3));
+        world.init_resource::<NextState<AppState>>();
+        world.init_resource::<Events<AllWavesCleared>>();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(check_game_over.run_if(game_undecided));
+
+        world.send_event(AllWavesCleared);
+        schedule.run(&mut world);
+        assert!(matches!(*world.resource::<NextState<AppState>>(), NextState::Pending(AppState::Victory)));
+
+        // enemies still walking at higher game speeds reach the finish on a later step of the same frame
+        world.insert_resource(Lives(0));
+        schedule.run(&mut world);
+        assert!(matches!(*world.resource::<NextState<AppState>>(), NextState::Pending(AppState::Victory)));
+    }
+}
@@ -0,0 +1,271 @@
+use bevy::prelude::*;
+
+use super::{
+    combat::DamageEnemy,
+    damage::DamageType,
+    status::{ApplyStatus, StatusEffect, StatusEffects},
+    enemy::{path_position, Enemy, Flying, PathProgress, ENEMY_HEIGHT},
+    GameScoped,
+};
+use crate::tilemap::{EnemyPath, TILE_SCALE};
+
+pub const BOLT_COLOR: Color = Color::srgb(0.95, 0.9, 0.3);
+pub const SHELL_COLOR: Color = Color::srgb(0.2, 0.2, 0.2);
+/// Peak height of a ballistic arc per tile travelled
+const ARC_HEIGHT: f32 = 0.35;
+
+/// How a projectile travels to its target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectileStyle {
+    /// Follows the enemy until it hits, removed when the enemy dies first
+    Homing,
+    /// Arcs to where the enemy is predicted to be when it lands, then hits whatever is there
+    Ballistic,
+}
+
+/// Projectile fired by a tower, `speed` in tiles per second and `splash_radius` in tiles
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProjectileSpec {
+    pub style: ProjectileStyle,
+    pub speed: f32,
+    pub splash_radius: Option<f32>,
+}
+
+/// Request to fire a projectile from `origin` at an enemy
+#[derive(Debug, Clone, Copy, Event)]
+pub struct FireProjectile {
+    pub origin: Vec3,
+    pub target: Entity,
+    pub damage: f32,
+    pub damage_type: DamageType,
+    pub spec: ProjectileSpec,
+    pub effect: Option<StatusEffect>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Flight {
+    Homing { target: Entity },
+    Ballistic { start: Vec3, end: Vec3, duration: f32, elapsed: f32 },
+}
+
+#[derive(Debug, Component, Clone, Copy)]
+pub struct Projectile {
+    flight: Flight,
+    /// Enemy the projectile was fired at, hit directly when there is no splash
+    target: Entity,
+    damage: f32,
+    damage_type: DamageType,
+    effect: Option<StatusEffect>,
+    speed: f32,
+    splash_radius: Option<f32>,
+}
+
+#[derive(Debug, Resource)]
+pub struct ProjectileAssets {
+    mesh: Handle<Mesh>,
+    bolt: Handle<StandardMaterial>,
+    shell: Handle<StandardMaterial>,
+}
+
+pub(super) fn setup_projectile_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(ProjectileAssets {
+        mesh: meshes.add(Sphere::new(0.08 * TILE_SCALE)),
+        bolt: materials.add(BOLT_COLOR),
+        shell: materials.add(SHELL_COLOR),
+    });
+}
+
+/// Damage dealt by a splash `distance` away from the impact, full at the centre down to half at the edge
+pub fn splash_damage(damage: f32, radius: f32, distance: f32) -> f32 {
+    if distance > radius {
+        return 0.0;
+    }
+    damage * (1.0 - 0.5 * distance / radius)
+}
+
+/// Path progress at which a projectile fired from `origin` meets an enemy walking the path,
+/// refined a few times since the flight time depends on where the enemy is met
+pub fn predict_progress(path: &[IVec2], origin: Vec3, progress: f32, enemy_speed: f32, speed: f32) -> f32 {
+    let mut predicted = progress;
+    for _ in 0..3 {
+        let Some(pos) = path_position(path, predicted) else {
+            return progress;
+        };
+        let flight_time = origin.xz().distance(pos.xz()) / (speed * TILE_SCALE);
+        predicted = progress + enemy_speed * flight_time;
+    }
+    predicted
+}
+
+/// Point along a ballistic arc, `t` from 0 at `start` to 1 at `end`
+pub fn arc_position(start: Vec3, end: Vec3, t: f32) -> Vec3 {
+    let peak = start.xz().distance(end.xz()) * ARC_HEIGHT;
+    start.lerp(end, t) + Vec3::Y * peak * 4.0 * t * (1.0 - t)
+}
+
+pub(super) fn spawn_projectiles(
+    mut commands: Commands,
+    mut ev_fire: EventReader<FireProjectile>,
+    enemies: Query<(&Enemy, &PathProgress, &Transform)>,
+    effects: Query<&StatusEffects>,
+    enemy_path: Res<EnemyPath>,
+    assets: Res<ProjectileAssets>,
+) {
+    for fire in ev_fire.read() {
+        let Ok((enemy, progress, transform)) = enemies.get(fire.target) else {
+            continue;
+        };
+
+        let (flight, material) = match fire.spec.style {
+            ProjectileStyle::Homing => (Flight::Homing { target: fire.target }, assets.bolt.clone()),
+            ProjectileStyle::Ballistic => {
+                let path = enemy_path.tiles();
+                // slowed and stunned enemies are met where they will have crawled to
+                let speed_multiplier = effects.get(fire.target).map_or(1.0, StatusEffects::speed_multiplier);
+                let enemy_speed = enemy.speed * speed_multiplier;
+                let predicted = predict_progress(path, fire.origin, progress.0, enemy_speed, fire.spec.speed);
+                let end = path_position(path, predicted)
+                    .map_or(transform.translation, |pos| pos + Vec3::Y * ENEMY_HEIGHT);
+                let duration = fire.origin.xz().distance(end.xz()) / (fire.spec.speed * TILE_SCALE);
+                let flight = Flight::Ballistic {
+                    start: fire.origin,
+                    end,
+                    duration,
+                    elapsed: 0.0,
+                };
+                (flight, assets.shell.clone())
+            }
+        };
+
+        commands.spawn((
+            Projectile {
+                flight,
+                target: fire.target,
+                damage: fire.damage,
+                damage_type: fire.damage_type,
+                effect: fire.effect,
+                speed: fire.spec.speed,
+                splash_radius: fire.spec.splash_radius,
+            },
+            Mesh3d(assets.mesh.clone()),
+            MeshMaterial3d(material),
+            Transform::from_translation(fire.origin),
+            GameScoped,
+        ));
+    }
+}
+
+/// Fly every projectile one fixed step, dealing damage and despawning it on impact
+pub(super) fn move_projectiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut projectiles: Query<(Entity, &mut Projectile, &mut Transform), Without<Enemy>>,
+    enemies: Query<(Entity, &Enemy, &Transform, Has<Flying>)>,
+    mut ev_damage: EventWriter<DamageEnemy>,
+    mut ev_apply_status: EventWriter<ApplyStatus>,
+) {
+    let delta = time.delta_secs();
+    for (ent, mut projectile, mut transform) in projectiles.iter_mut() {
+        let step = projectile.speed * TILE_SCALE * delta;
+        let impact = match &mut projectile.flight {
+            Flight::Homing { target } => {
+                let Ok((_ent, _enemy, enemy_transform, _flying)) = enemies.get(*target) else {
+                    // the target died before the projectile reached it
+                    commands.entity(ent).despawn();
+                    continue;
+                };
+                let to_target = enemy_transform.translation - transform.translation;
+                if to_target.length() <= step {
+                    transform.translation = enemy_transform.translation;
+                    true
+                } else {
+                    transform.translation += to_target.normalize() * step;
+                    false
+                }
+            }
+            Flight::Ballistic {
+                start,
+                end,
+                duration,
+                elapsed,
+            } => {
+                *elapsed += delta;
+                let t = if *duration > 0.0 { (*elapsed / *duration).min(1.0) } else { 1.0 };
+                transform.translation = arc_position(*start, *end, t);
+                t >= 1.0
+            }
+        };
+        if !impact {
+            continue;
+        }
+
+        commands.entity(ent).despawn();
+        let hits = match projectile.splash_radius {
+            // splashes stay on the ground
+            Some(radius) => {
+                let mut hits = enemies
+                    .iter()
+                    .filter(|(_ent, _enemy, _transform, flying)| !flying)
+                    .map(|(ent, enemy, enemy_transform, _flying)| {
+                        let distance = transform.translation.distance(enemy_transform.translation) / TILE_SCALE;
+                        (enemy.order, ent, splash_damage(projectile.damage, radius, distance))
+                    })
+                    .filter(|(_order, _ent, amount)| *amount > 0.0)
+                    .collect::<Vec<(u64, Entity, f32)>>();
+                // query order is not stable across runs, damage is dealt in spawn order for replays
+                hits.sort_by_key(|(order, _ent, _amount)| *order);
+                hits.into_iter().map(|(_order, ent, amount)| (ent, amount)).collect()
+            }
+            None if enemies.contains(projectile.target) => vec![(projectile.target, projectile.damage)],
+            None => vec![],
+        };
+        for (enemy, amount) in hits {
+            ev_damage.write(DamageEnemy {
+                enemy,
+                amount,
+                damage_type: projectile.damage_type,
+            });
+            if let Some(effect) = projectile.effect {
+                ev_apply_status.write(ApplyStatus { enemy, effect });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::tile_center;
+
+    #[test]
+    fn splash_falls_off_towards_the_edge() {
+        assert_eq!(splash_damage(10.0, 2.0, 0.0), 10.0);
+        assert_eq!(splash_damage(10.0, 2.0, 1.0), 7.5);
+        assert_eq!(splash_damage(10.0, 2.0, 2.0), 5.0);
+        assert_eq!(splash_damage(10.0, 2.0, 2.1), 0.0);
+    }
+
+    #[test]
+    fn ballistic_shots_lead_their_target() {
+        let path = (0..10).map(|x| IVec2::new(x, 0)).collect::<Vec<IVec2>>();
+        let origin = tile_center(IVec2::new(2, 2));
+
+        // a standing enemy is hit where it stands
+        assert_eq!(predict_progress(&path, origin, 2.0, 0.0, 4.0), 2.0);
+        // a moving enemy is met further along, where it walked d tiles while the shot flew sqrt(d² + 4)
+        let predicted = predict_progress(&path, origin, 2.0, 2.0, 4.0);
+        assert!((predicted - (2.0 + 2.0 / 3f32.sqrt())).abs() < 0.02, "predicted {predicted}");
+    }
+
+    #[test]
+    fn arcs_start_and_end_on_their_points() {
+        let (start, end) = (Vec3::new(0.0, 5.0, 0.0), Vec3::new(20.0, 3.0, 0.0));
+        assert_eq!(arc_position(start, end, 0.0), start);
+        assert_eq!(arc_position(start, end, 1.0), end);
+        assert!(arc_position(start, end, 0.5).y > start.y);
+    }
+}
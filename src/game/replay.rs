@@ -0,0 +1,530 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    combat::CycleTargetPriority,
+    economy::{SellTower, Wallet},
+    enemy::{EnemyCounter, EnemyKind, SpawnEnemy},
+    outcome::Lives,
+    save::{PendingSave, SavedGame},
+    tower::{BuildTower, Tower},
+    upgrade::UpgradeTower,
+    wave::{map_waves, SendNextWave, WaveFile},
+};
+use crate::{
+    editor::map_dialog::{capture_map_file, CurrentMapFile},
+    map_file::{embedded_map, MapFile},
+    sidecar::{read_sidecar_file, sidecar_file_path, write_sidecar_file},
+    tilemap::{update_gametilemap, EnemyPath, GameTilemap, MapSize, MapState, TowerType, UpdateColorMap},
+    AppState,
+};
+
+/// Extension of the replay stored next to a map
+const REPLAY_EXTENSION: &str = "replay.txt";
+
+/// Random numbers for the simulation, seeded at the start of every game and stored in its replay
+/// Gameplay randomness has to come from here for a replay to reproduce it
+#[derive(Debug, Resource, Clone, Default, PartialEq, Eq)]
+pub struct GameRng {
+    state: u64,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng { state: seed }
+    }
+
+    /// SplitMix64, small and good enough for gameplay
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform in `[min, max)`
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+/// Fixed steps simulated since the game started, the commands of a replay are stamped with it
+#[derive(Debug, Resource, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SimTick(pub u64);
+
+/// Command given by the player that changes the outcome of a game, towers are found by their tile
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PlayerCommand {
+    SendWave,
+    Build { loc: IVec2, kind: TowerType },
+    Sell { loc: IVec2 },
+    Upgrade { loc: IVec2, choice: usize },
+    /// Switch a tower to its next target priority
+    Target { loc: IVec2 },
+    /// Enemy sent outside of a wave with the debug key
+    SpawnEnemy { kind: EnemyKind },
+}
+
+/// Command applied before simulating step `tick`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimedCommand {
+    pub tick: u64,
+    pub command: PlayerCommand,
+}
+
+/// State of the game on the step it was won or lost
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayOutcome {
+    pub tick: u64,
+    pub victory: bool,
+    pub gold: u32,
+    pub lives: u32,
+}
+
+/// Everything needed to play a game again to the same outcome
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    /// Map file played, `None` for a map that was never saved
+    pub map: Option<PathBuf>,
+    /// Map as it was played, restored before playing back so edits made since do not change the outcome
+    #[serde(with = "embedded_map")]
+    pub map_file: MapFile,
+    /// Waves, starting gold and lives of the map as they were played
+    pub waves: WaveFile,
+    pub seed: u64,
+    /// Game save the game was started from
+    #[serde(default)]
+    pub start: Option<SavedGame>,
+    pub commands: Vec<TimedCommand>,
+    /// Missing when the replay was saved before the game ended
+    #[serde(default)]
+    pub outcome: Option<ReplayOutcome>,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Replay {
+            map: None,
+            map_file: MapFile::empty(),
+            waves: WaveFile::default(),
+            seed: 0,
+            start: None,
+            commands: vec![],
+            outcome: None,
+        }
+    }
+}
+
+/// Replay stored next to a map, `maps/simple1.txt` uses `maps/simple1.replay.txt`
+pub fn replay_file_path(map_path: Option<&Path>) -> PathBuf {
+    sidecar_file_path(map_path, REPLAY_EXTENSION)
+}
+
+/// Replay of the running game, recorded from the player or played back
+#[derive(Debug, Resource, Default)]
+pub struct ActiveReplay {
+    pub replay: Replay,
+    /// Index of the next command to play back, `None` while recording
+    playback: Option<usize>,
+    /// Whether the outcome of the game was decided
+    finished: bool,
+}
+
+impl ActiveReplay {
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
+    }
+}
+
+/// Request to write the replay of the running game
+#[derive(Debug, Clone, Copy, Event)]
+pub struct SaveReplayEvent;
+
+/// Request to restart the map and play back its replay
+#[derive(Debug, Clone, Copy, Event)]
+pub struct PlayReplayEvent;
+
+/// Replay to play back once the game has been reset
+#[derive(Debug, Resource, Default)]
+pub struct PendingReplay(pub Option<Replay>);
+
+/// Events of the commands a player can give, see `PlayerCommand`
+#[derive(SystemParam)]
+pub(super) struct CommandEvents<'w> {
+    send_wave: ResMut<'w, Events<SendNextWave>>,
+    build: ResMut<'w, Events<BuildTower>>,
+    sell: ResMut<'w, Events<SellTower>>,
+    upgrade: ResMut<'w, Events<UpgradeTower>>,
+    target: ResMut<'w, Events<CycleTargetPriority>>,
+    spawn: ResMut<'w, Events<SpawnEnemy>>,
+}
+
+impl CommandEvents<'_> {
+    fn clear(&mut self) {
+        self.send_wave.clear();
+        self.build.clear();
+        self.sell.clear();
+        self.upgrade.clear();
+        self.target.clear();
+        self.spawn.clear();
+    }
+}
+
+#[derive(SystemParam)]
+pub(super) struct CommandWriters<'w> {
+    send_wave: EventWriter<'w, SendNextWave>,
+    build: EventWriter<'w, BuildTower>,
+    sell: EventWriter<'w, SellTower>,
+    upgrade: EventWriter<'w, UpgradeTower>,
+    target: EventWriter<'w, CycleTargetPriority>,
+    spawn: EventWriter<'w, SpawnEnemy>,
+}
+
+#[derive(SystemParam)]
+pub(super) struct CommandReaders<'w, 's> {
+    send_wave: EventReader<'w, 's, SendNextWave>,
+    build: EventReader<'w, 's, BuildTower>,
+    sell: EventReader<'w, 's, SellTower>,
+    upgrade: EventReader<'w, 's, UpgradeTower>,
+    target: EventReader<'w, 's, CycleTargetPriority>,
+    spawn: EventReader<'w, 's, SpawnEnemy>,
+}
+
+/// Seed given to a new game
+fn new_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+/// Reset the simulation clock and seed it, then either record the player or play back the pending replay
+/// on the map it was recorded on. Runs after the game was reset and before the waves are loaded
+pub(super) fn start_replay(
+    mut commands: Commands,
+    mut pending_replay: ResMut<PendingReplay>,
+    mut pending_save: ResMut<PendingSave>,
+    current_file: Res<CurrentMapFile>,
+    mut gtm: ResMut<GameTilemap>,
+    mut map_size: ResMut<MapSize>,
+    mut enemy_path: ResMut<EnemyPath>,
+    mut map_nextstate: ResMut<NextState<MapState>>,
+    mut ev_update_colormap: EventWriter<UpdateColorMap>,
+) {
+    let active = match pending_replay.0.take() {
+        Some(replay) => {
+            info!("Playing back replay with seed {}", replay.seed);
+            if replay.map_file != capture_map_file(&gtm, &enemy_path, &map_size) {
+                info!("Restoring the map the replay was recorded on");
+                update_gametilemap(
+                    &mut gtm,
+                    &mut map_size,
+                    &mut enemy_path,
+                    &replay.map_file,
+                    &mut map_nextstate,
+                    &mut ev_update_colormap,
+                );
+            }
+            pending_save.0 = replay.start.clone();
+            ActiveReplay {
+                replay,
+                playback: Some(0),
+                finished: false,
+            }
+        }
+        None => {
+            let seed = new_seed();
+            info!("Starting game with seed {seed}");
+            ActiveReplay {
+                replay: Replay {
+                    map: current_file.0.clone(),
+                    map_file: capture_map_file(&gtm, &enemy_path, &map_size),
+                    waves: map_waves(current_file.0.as_deref()),
+                    seed,
+                    start: pending_save.0.clone(),
+                    ..default()
+                },
+                ..default()
+            }
+        }
+    };
+    commands.insert_resource(GameRng::new(active.replay.seed));
+    commands.insert_resource(SimTick::default());
+    commands.insert_resource(EnemyCounter::default());
+    commands.insert_resource(active);
+}
+
+/// Drop whatever the player clicked while a replay plays back, only the commands of the replay are applied
+pub(super) fn drop_player_commands(active: Res<ActiveReplay>, mut events: CommandEvents) {
+    if active.is_playing() {
+        events.clear();
+    }
+}
+
+/// Turn the commands due on this step back into events
+pub(super) fn play_commands(
+    mut active: ResMut<ActiveReplay>,
+    tick: Res<SimTick>,
+    towers: Query<(Entity, &Tower)>,
+    mut events: CommandWriters,
+) {
+    let Some(next) = active.playback else {
+        return;
+    };
+
+    let due = active.replay.commands[next..]
+        .iter()
+        .take_while(|timed| timed.tick <= tick.0)
+        .copied()
+        .collect::<Vec<TimedCommand>>();
+    let tower_at = |loc: IVec2| {
+        let tower = towers.iter().find(|(_ent, tower)| tower.loc == loc).map(|(ent, _tower)| ent);
+        if tower.is_none() {
+            warn!("Replay diverged, there is no tower at {loc} on step {}", tick.0);
+        }
+        tower
+    };
+    for TimedCommand { command, .. } in due.iter() {
+        match *command {
+            PlayerCommand::SendWave => {
+                events.send_wave.write(SendNextWave);
+            }
+            PlayerCommand::Build { loc, kind } => {
+                events.build.write(BuildTower { loc, kind });
+            }
+            PlayerCommand::Sell { loc } => {
+                if let Some(tower) = tower_at(loc) {
+                    events.sell.write(SellTower { tower });
+                }
+            }
+            PlayerCommand::Upgrade { loc, choice } => {
+                if let Some(tower) = tower_at(loc) {
+                    events.upgrade.write(UpgradeTower { tower, choice });
+                }
+            }
+            PlayerCommand::Target { loc } => {
+                if let Some(tower) = tower_at(loc) {
+                    events.target.write(CycleTargetPriority { tower });
+                }
+            }
+            PlayerCommand::SpawnEnemy { kind } => {
+                events.spawn.write(SpawnEnemy { kind, wave: None });
+            }
+        }
+    }
+    active.playback = Some(next + due.len());
+}
+
+/// Stamp the commands given by the player with the step they are applied on
+/// Commands of each kind are applied in turn, so they are recorded in that order too
+pub(super) fn record_commands(
+    mut active: ResMut<ActiveReplay>,
+    tick: Res<SimTick>,
+    towers: Query<&Tower>,
+    mut readers: CommandReaders,
+) {
+    let tower_loc = |tower: Entity| towers.get(tower).ok().map(|tower| tower.loc);
+    let mut recorded = vec![];
+    recorded.extend(readers.send_wave.read().map(|_ev| PlayerCommand::SendWave));
+    recorded.extend(readers.build.read().map(|build| PlayerCommand::Build {
+        loc: build.loc,
+        kind: build.kind,
+    }));
+    recorded.extend(
+        readers
+            .sell
+            .read()
+            .filter_map(|sell| tower_loc(sell.tower))
+            .map(|loc| PlayerCommand::Sell { loc }),
+    );
+    recorded.extend(readers.upgrade.read().filter_map(|upgrade| {
+        tower_loc(upgrade.tower).map(|loc| PlayerCommand::Upgrade {
+            loc,
+            choice: upgrade.choice,
+        })
+    }));
+    recorded.extend(
+        readers
+            .target
+            .read()
+            .filter_map(|target| tower_loc(target.tower))
+            .map(|loc| PlayerCommand::Target { loc }),
+    );
+    // enemies of a wave are sent by the simulation itself
+    recorded.extend(
+        readers
+            .spawn
+            .read()
+            .filter(|spawn| spawn.wave.is_none())
+            .map(|spawn| PlayerCommand::SpawnEnemy { kind: spawn.kind }),
+    );
+
+    // played back commands are already in the replay
+    if active.is_playing() || recorded.is_empty() {
+        return;
+    }
+    let commands = recorded.into_iter().map(|command| TimedCommand { tick: tick.0, command });
+    active.replay.commands.extend(commands);
+}
+
+pub(super) fn advance_tick(mut tick: ResMut<SimTick>) {
+    tick.0 += 1;
+}
+
+/// Once the game is won or lost, store the outcome in a recorded replay or compare it with a played back one
+/// Fixed steps can still run before the state changes, only the step deciding the game counts
+pub(super) fn finish_replay(
+    mut active: ResMut<ActiveReplay>,
+    tick: Res<SimTick>,
+    app_state: Res<NextState<AppState>>,
+    wallet: Res<Wallet>,
+    lives: Res<Lives>,
+) {
+    if active.finished {
+        return;
+    }
+    let victory = match app_state.as_ref() {
+        NextState::Pending(AppState::Victory) => true,
+        NextState::Pending(AppState::Defeat) => false,
+        _ => return,
+    };
+    active.finished = true;
+    let outcome = ReplayOutcome {
+        tick: tick.0,
+        victory,
+        gold: wallet.gold(),
+        lives: lives.0,
+    };
+
+    if active.is_playing() {
+        match active.replay.outcome {
+            Some(recorded) if recorded == outcome => info!("Replay reproduced its recorded outcome"),
+            Some(recorded) => warn!("Replay diverged, recorded {recorded:?} but played back to {outcome:?}"),
+            None => info!("Replay played back to {outcome:?}"),
+        }
+        return;
+    }
+    active.replay.outcome = Some(outcome);
+    let path = replay_file_path(active.replay.map.as_deref());
+    match write_sidecar_file(&path, &active.replay) {
+        Ok(()) => info!("Saved replay to {}", path.display()),
+        Err(e) => warn!("{e}"),
+    }
+}
+
+pub(super) fn save_replay(mut ev_save_replay: EventReader<SaveReplayEvent>, active: Res<ActiveReplay>) {
+    if ev_save_replay.read().last().is_none() {
+        return;
+    }
+    let path = replay_file_path(active.replay.map.as_deref());
+    match write_sidecar_file(&path, &active.replay) {
+        Ok(()) => info!("Saved replay to {}", path.display()),
+        Err(e) => warn!("{e}"),
+    }
+}
+
+/// Read the replay of the current map and restart the game to play it back
+pub(super) fn play_replay(
+    mut ev_play_replay: EventReader<PlayReplayEvent>,
+    current_file: Res<CurrentMapFile>,
+    mut pending: ResMut<PendingReplay>,
+    mut app_state: ResMut<NextState<AppState>>,
+) {
+    if ev_play_replay.read().last().is_none() {
+        return;
+    }
+    let path = replay_file_path(current_file.0.as_deref());
+    match read_sidecar_file::<Replay>(&path) {
+        Ok(replay) => {
+            info!("Loading replay from {}", path.display());
+            pending.0 = Some(replay);
+            app_state.set(AppState::ToGame);
+        }
+        Err(e) => warn!("{e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::{parse_map, SavedTileMap};
+
+    #[test]
+    fn same_seed_gives_same_numbers() {
+        let mut a = GameRng::new(42);
+        let mut b = GameRng::new(42);
+        let numbers = (0..8).map(|_| a.next_u64()).collect::<Vec<u64>>();
+        assert_eq!(numbers, (0..8).map(|_| b.next_u64()).collect::<Vec<u64>>());
+        assert_ne!(GameRng::new(43).next_u64(), numbers[0]);
+
+        for _ in 0..100 {
+            let value = a.range(2.0, 3.0);
+            assert!((2.0..3.0).contains(&value));
+        }
+    }
+
+    #[test]
+    fn replays_round_trip() {
+        let replay = Replay {
+            map: Some(PathBuf::from("maps/simple1.txt")),
+            map_file: MapFile::new(2, 1, SavedTileMap::new(), vec![IVec2::new(0, 0), IVec2::new(1, 0)]),
+            waves: WaveFile::default(),
+            seed: 7,
+            start: None,
+            commands: vec![
+                TimedCommand {
+                    tick: 0,
+                    command: PlayerCommand::Build {
+                        loc: IVec2::new(3, 4),
+                        kind: TowerType::T2,
+                    },
+                },
+                TimedCommand {
+                    tick: 120,
+                    command: PlayerCommand::SendWave,
+                },
+                TimedCommand {
+                    tick: 120,
+                    command: PlayerCommand::SpawnEnemy { kind: EnemyKind::Flyer },
+                },
+                TimedCommand {
+                    tick: 900,
+                    command: PlayerCommand::Upgrade {
+                        loc: IVec2::new(3, 4),
+                        choice: 0,
+                    },
+                },
+            ],
+            outcome: Some(ReplayOutcome {
+                tick: 4000,
+                victory: true,
+                gold: 35,
+                lives: 18,
+            }),
+        };
+        let json = serde_json::to_string(&replay).expect("replay should serialize");
+        assert_eq!(serde_json::from_str::<Replay>(&json).expect("replay should parse"), replay);
+
+        // maps recorded by older builds migrate like map files
+        let legacy = std::fs::read_to_string("maps/simple1.txt").expect("map file missing");
+        let mut value = serde_json::to_value(&replay).expect("replay should serialize");
+        value["map_file"] = serde_json::from_str(&legacy).expect("map file should be JSON");
+        let migrated = serde_json::from_value::<Replay>(value).expect("replay should parse");
+        assert_eq!(migrated.map_file, parse_map(&legacy).expect("legacy map should migrate"));
+
+        assert_eq!(
+            replay_file_path(Some(Path::new("maps/simple1.txt"))),
+            PathBuf::from("maps/simple1.replay.txt")
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    combat::{TargetPriority, Targeting},
+    economy::{TowerValue, Wallet},
+    outcome::Lives,
+    tower::{spawn_tower, Tower, TowerAssets},
+    upgrade::TowerLevel,
+    wave::WaveScheduler,
+};
+use crate::{
+    editor::map_dialog::CurrentMapFile,
+    sidecar::{read_sidecar_file, sidecar_file_path, write_sidecar_file},
+    tilemap::{GameTilemap, SetTile, TileType, TowerType},
+    AppState,
+};
+
+/// Extension of the game save stored next to a map
+const SAVE_EXTENSION: &str = "save.txt";
+
+/// Tower as stored in a game save
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedTower {
+    pub loc: IVec2,
+    pub kind: TowerType,
+    pub level: TowerLevel,
+    pub priority: TargetPriority,
+    /// Gold spent on the tower, see `TowerValue`
+    pub value: u32,
+}
+
+/// Progress through a map, taken between waves since enemies and projectiles are not stored
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedGame {
+    pub gold: u32,
+    pub lives: u32,
+    /// Waves sent and cleared before saving
+    pub waves_cleared: usize,
+    pub towers: Vec<SavedTower>,
+}
+
+/// Game save stored next to a map, `maps/simple1.txt` uses `maps/simple1.save.txt`
+pub fn save_file_path(map_path: Option<&Path>) -> PathBuf {
+    sidecar_file_path(map_path, SAVE_EXTENSION)
+}
+
+/// Request to save the running game
+#[derive(Debug, Clone, Copy, Event)]
+pub struct SaveGameEvent;
+
+/// Request to restart the map from its game save
+#[derive(Debug, Clone, Copy, Event)]
+pub struct LoadGameEvent;
+
+/// Game save to restore once the game has been reset
+#[derive(Debug, Resource, Default)]
+pub struct PendingSave(pub Option<SavedGame>);
+
+pub(super) fn save_game(
+    mut ev_save_game: EventReader<SaveGameEvent>,
+    current_file: Res<CurrentMapFile>,
+    wallet: Res<Wallet>,
+    lives: Res<Lives>,
+    scheduler: Res<WaveScheduler>,
+    towers: Query<(&Tower, &TowerLevel, &Targeting, &TowerValue)>,
+) {
+    if ev_save_game.read().last().is_none() {
+        return;
+    }
+    if !scheduler.is_idle() {
+        info!("Unable to save the game while a wave is running");
+        return;
+    }
+
+    let mut saved_towers = towers
+        .iter()
+        .map(|(tower, level, targeting, value)| SavedTower {
+            loc: tower.loc,
+            kind: tower.kind,
+            level: *level,
+            priority: targeting.priority,
+            value: value.0,
+        })
+        .collect::<Vec<SavedTower>>();
+    // keep the file contents stable between saves
+    saved_towers.sort_by_key(|tower| (tower.loc.x, tower.loc.y));
+    let saved = SavedGame {
+        gold: wallet.gold(),
+        lives: lives.0,
+        waves_cleared: scheduler.sent(),
+        towers: saved_towers,
+    };
+
+    let path = save_file_path(current_file.0.as_deref());
+    match write_sidecar_file(&path, &saved) {
+        Ok(()) => info!("Saved game to {}", path.display()),
+        Err(e) => warn!("{e}"),
+    }
+}
+
+/// Read the game save of the current map and restart the game from it
+pub(super) fn load_game(
+    mut ev_load_game: EventReader<LoadGameEvent>,
+    current_file: Res<CurrentMapFile>,
+    mut pending: ResMut<PendingSave>,
+    mut app_state: ResMut<NextState<AppState>>,
+) {
+    if ev_load_game.read().last().is_none() {
+        return;
+    }
+    let path = save_file_path(current_file.0.as_deref());
+    match read_sidecar_file::<SavedGame>(&path) {
+        Ok(saved) => {
+            info!("Loading game from {}", path.display());
+            pending.0 = Some(saved);
+            app_state.set(AppState::ToGame);
+        }
+        Err(e) => warn!("{e}"),
+    }
+}
+
+/// Runs after the game was reset and the waves of the map loaded
+pub(super) fn restore_game(
+    mut commands: Commands,
+    mut pending: ResMut<PendingSave>,
+    mut wallet: ResMut<Wallet>,
+    mut lives: ResMut<Lives>,
+    mut scheduler: ResMut<WaveScheduler>,
+    gtm: Res<GameTilemap>,
+    assets: Res<TowerAssets>,
+    mut ev_set_tile: EventWriter<SetTile>,
+) {
+    let Some(saved) = pending.0.take() else {
+        return;
+    };
+    *wallet = Wallet::new(saved.gold);
+    lives.0 = saved.lives;
+    scheduler.skip_waves(saved.waves_cleared);
+
+    let mut restored = Vec::new();
+    for tower in saved.towers {
+        if !tower.level.is_valid() {
+            warn!("Unable to restore {:?} at {}, {:?} is not a tower level", tower.kind, tower.loc, tower.level);
+            continue;
+        }
+        if restored.contains(&tower.loc) {
+            warn!("Unable to restore {:?} at {}, another tower stands there", tower.kind, tower.loc);
+            continue;
+        }
+        // tower tiles were turned back into Free tiles when the game was reset
+        if !matches!(gtm.0.get(&tower.loc), Some(TileType::Free | TileType::Tower(_))) {
+            warn!("Unable to restore {:?} at {}, the tile is no longer free", tower.kind, tower.loc);
+            continue;
+        }
+        restored.push(tower.loc);
+        spawn_tower(
+            &mut commands,
+            &assets,
+            tower.kind,
+            tower.loc,
+            tower.level,
+            tower.priority,
+            tower.value,
+        );
+        ev_set_tile.write(SetTile {
+            loc: tower.loc,
+            tile: TileType::Tower(tower.kind),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_round_trip() {
+        let saved = SavedGame {
+            gold: 230,
+            lives: 12,
+            waves_cleared: 2,
+            towers: vec![SavedTower {
+                loc: IVec2::new(4, 2),
+                kind: TowerType::T2,
+                level: TowerLevel {
+                    tier: 3,
+                    specialization: Some(1),
+                },
+                priority: TargetPriority::Strongest,
+                value: 550,
+            }],
+        };
+        let json = serde_json::to_string(&saved).expect("save should serialize");
+        assert_eq!(serde_json::from_str::<SavedGame>(&json).expect("save should parse"), saved);
+
+        assert_eq!(
+            save_file_path(Some(Path::new("maps/simple1.txt"))),
+            PathBuf::from("maps/simple1.save.txt")
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use bevy::prelude::*;
+
+/// Speed multipliers the player can pick from
+pub const SPEEDS: [u32; 3] = [1, 2, 3];
+
+/// How fast the simulation runs, applied to the virtual clock driving `FixedUpdate`
+/// Every fixed step lasts the same, a higher multiplier only runs more of them per frame
+#[derive(Debug, Resource, Clone, Copy, PartialEq, Eq)]
+pub struct GameSpeed {
+    pub paused: bool,
+    pub multiplier: u32,
+}
+
+impl Default for GameSpeed {
+    fn default() -> Self {
+        GameSpeed {
+            paused: false,
+            multiplier: 1,
+        }
+    }
+}
+
+impl GameSpeed {
+    /// Run at `multiplier` times the normal speed, resuming a paused game
+    pub fn set(&mut self, multiplier: u32) {
+        self.paused = false;
+        self.multiplier = multiplier;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Text shown in the HUD
+    pub fn label(&self) -> String {
+        if self.paused {
+            "Paused".to_string()
+        } else {
+            format!("Speed {}x", self.multiplier)
+        }
+    }
+}
+
+/// Space, P and Escape pause and resume the game, the number keys pick a speed
+/// The game stays in place while paused, the pause menu would restart it when resumed
+pub(super) fn speed_keys(keys: Res<ButtonInput<KeyCode>>, mut speed: ResMut<GameSpeed>) {
+    if keys.any_just_pressed([KeyCode::Space, KeyCode::KeyP, KeyCode::Escape]) {
+        speed.toggle_pause();
+    }
+    let digits = [KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3];
+    for (key, multiplier) in digits.into_iter().zip(SPEEDS) {
+        if keys.just_pressed(key) {
+            speed.set(multiplier);
+        }
+    }
+}
+
+pub(super) fn reset_game_speed(mut speed: ResMut<GameSpeed>) {
+    *speed = GameSpeed::default();
+}
+
+/// Scale the virtual clock, `FixedUpdate` runs as many steps as the scaled clock has advanced
+pub(super) fn apply_game_speed(speed: Res<GameSpeed>, mut time: ResMut<Time<Virtual>>) {
+    time.set_relative_speed(speed.multiplier as f32);
+    if speed.paused {
+        time.pause();
+    } else {
+        time.unpause();
+    }
+}
+
+/// Outside of the game the virtual clock runs at normal speed
+pub(super) fn restore_virtual_time(mut time: ResMut<Time<Virtual>>) {
+    time.set_relative_speed(1.0);
+    time.unpause();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picking_a_speed_resumes_the_game() {
+        let mut speed = GameSpeed::default();
+        speed.set(3);
+        speed.toggle_pause();
+        assert_eq!(speed.label(), "Paused");
+        speed.toggle_pause();
+        assert_eq!(speed.label(), "Speed 3x");
+        speed.toggle_pause();
+        speed.set(2);
+        assert_eq!(
+            speed,
+            GameSpeed {
+                paused: false,
+                multiplier: 2
+            }
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use super::{combat::DamageEnemy, damage::DamageType};
+use crate::tilemap::TILE_SCALE;
+
+/// Most instances of one effect an enemy can carry when the effect stacks
+pub const MAX_STACKS: usize = 5;
+/// Highest share of its speed an enemy can lose to slows
+pub const MAX_SLOW: f32 = 0.75;
+/// Height of the effect markers above the enemy centre
+const MARKER_HEIGHT: f32 = 0.4 * TILE_SCALE;
+/// Gap between the markers of different effects
+const MARKER_SPACING: f32 = 0.15 * TILE_SCALE;
+
+/// What an effect does to the enemy, the meaning of `strength` depends on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatusKind {
+    /// Share of the speed removed
+    Slow,
+    /// Damage of the given type dealt every second
+    DamageOverTime(DamageType),
+    /// Stops the enemy, `strength` is unused
+    Stun,
+    /// Armor removed
+    ArmorReduction,
+}
+
+impl StatusKind {
+    /// Every kind of effect, used to build the marker materials
+    pub fn all() -> Vec<StatusKind> {
+        let damage_over_time = [DamageType::Physical, DamageType::Magic, DamageType::Fire, DamageType::Frost]
+            .into_iter()
+            .map(StatusKind::DamageOverTime);
+        [StatusKind::Slow, StatusKind::Stun, StatusKind::ArmorReduction]
+            .into_iter()
+            .chain(damage_over_time)
+            .collect()
+    }
+
+    /// Color of the marker shown above affected enemies
+    pub fn color(&self) -> Color {
+        match self {
+            StatusKind::Slow => Color::srgb(0.4, 0.6, 1.0),
+            StatusKind::DamageOverTime(DamageType::Physical) => Color::srgb(0.6, 0.05, 0.05),
+            StatusKind::DamageOverTime(DamageType::Magic) => Color::srgb(0.7, 0.3, 0.95),
+            StatusKind::DamageOverTime(DamageType::Fire) => Color::srgb(1.0, 0.45, 0.05),
+            StatusKind::DamageOverTime(DamageType::Frost) => Color::srgb(0.75, 0.95, 1.0),
+            StatusKind::Stun => Color::srgb(1.0, 0.9, 0.2),
+            StatusKind::ArmorReduction => Color::srgb(0.45, 0.45, 0.45),
+        }
+    }
+}
+
+/// How a new application combines with an effect of the same kind already on the enemy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stacking {
+    /// Replace the effect, restarting its duration
+    Refresh,
+    /// Add another instance up to `MAX_STACKS`, their strengths add up
+    Stack,
+    /// Keep the stronger strength and the longer duration of the two
+    MaxOf,
+}
+
+/// Timed effect applied by a tower hit, `duration` in seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    pub strength: f32,
+    pub duration: f32,
+    pub stacking: Stacking,
+}
+
+/// Effect on an enemy along with the seconds left before it wears off
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActiveEffect {
+    pub effect: StatusEffect,
+    pub remaining: f32,
+}
+
+/// Effects currently on an enemy
+#[derive(Debug, Component, Clone, Default, PartialEq)]
+pub struct StatusEffects(Vec<ActiveEffect>);
+
+impl StatusEffects {
+    pub fn apply(&mut self, effect: StatusEffect) {
+        let fresh = ActiveEffect {
+            effect,
+            remaining: effect.duration,
+        };
+        let stacks = self.0.iter().filter(|active| active.effect.kind == effect.kind).count();
+        let mut same_kind = self.0.iter_mut().filter(|active| active.effect.kind == effect.kind);
+        match effect.stacking {
+            Stacking::Refresh => match same_kind.next() {
+                Some(active) => *active = fresh,
+                None => self.0.push(fresh),
+            },
+            Stacking::Stack if stacks < MAX_STACKS => self.0.push(fresh),
+            // replace the instance closest to wearing off
+            Stacking::Stack => {
+                if let Some(oldest) = same_kind.min_by(|a, b| a.remaining.total_cmp(&b.remaining)) {
+                    *oldest = fresh;
+                }
+            }
+            Stacking::MaxOf => match same_kind.next() {
+                Some(active) => {
+                    active.effect.strength = active.effect.strength.max(effect.strength);
+                    active.remaining = active.remaining.max(effect.duration);
+                }
+                None => self.0.push(fresh),
+            },
+        }
+    }
+
+    /// Count down every effect by `delta` seconds, removing those that wore off
+    pub fn tick(&mut self, delta: f32) {
+        for active in self.0.iter_mut() {
+            active.remaining -= delta;
+        }
+        self.0.retain(|active| active.remaining > 0.0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Summed strength of every instance of one kind of effect
+    pub fn strength(&self, kind: StatusKind) -> f32 {
+        self.0
+            .iter()
+            .filter(|active| active.effect.kind == kind)
+            .map(|active| active.effect.strength)
+            .sum()
+    }
+
+    /// Kinds of effect on the enemy in the order they were first applied
+    pub fn kinds(&self) -> Vec<StatusKind> {
+        let mut kinds = Vec::new();
+        for active in self.0.iter() {
+            if !kinds.contains(&active.effect.kind) {
+                kinds.push(active.effect.kind);
+            }
+        }
+        kinds
+    }
+
+    /// Share of its speed the enemy keeps, 0 while stunned
+    pub fn speed_multiplier(&self) -> f32 {
+        if self.kinds().contains(&StatusKind::Stun) {
+            return 0.0;
+        }
+        1.0 - self.strength(StatusKind::Slow).min(MAX_SLOW)
+    }
+
+    /// Armor left once armor reductions are taken off
+    pub fn reduced_armor(&self, armor: f32) -> f32 {
+        (armor - self.strength(StatusKind::ArmorReduction)).max(0.0)
+    }
+}
+
+/// Request to apply an effect to an enemy
+#[derive(Debug, Clone, Copy, Event)]
+pub struct ApplyStatus {
+    pub enemy: Entity,
+    pub effect: StatusEffect,
+}
+
+/// Marker shown above an enemy while an effect of this kind is on it
+#[derive(Debug, Component, Clone, Copy)]
+pub struct StatusMarker(pub StatusKind);
+
+#[derive(Debug, Resource)]
+pub struct StatusAssets {
+    mesh: Handle<Mesh>,
+    materials: HashMap<StatusKind, Handle<StandardMaterial>>,
+}
+
+pub(super) fn setup_status_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(StatusAssets {
+        mesh: meshes.add(Cuboid::from_length(0.1 * TILE_SCALE)),
+        materials: StatusKind::all()
+            .into_iter()
+            .map(|kind| (kind, materials.add(kind.color())))
+            .collect(),
+    });
+}
+
+pub(super) fn apply_status_effects(
+    mut ev_apply_status: EventReader<ApplyStatus>,
+    mut enemies: Query<&mut StatusEffects>,
+) {
+    for ApplyStatus { enemy, effect } in ev_apply_status.read() {
+        if let Ok(mut effects) = enemies.get_mut(*enemy) {
+            effects.apply(*effect);
+        }
+    }
+}
+
+/// Deal the damage over time of every effect and count their durations down
+pub(super) fn tick_status_effects(
+    time: Res<Time>,
+    mut enemies: Query<(Entity, &mut StatusEffects)>,
+    mut ev_damage: EventWriter<DamageEnemy>,
+) {
+    for (enemy, mut effects) in enemies.iter_mut() {
+        // skip the change detection of enemies without effects
+        if effects.is_empty() {
+            continue;
+        }
+        for kind in effects.kinds() {
+            if let StatusKind::DamageOverTime(damage_type) = kind {
+                ev_damage.write(DamageEnemy {
+                    enemy,
+                    amount: effects.strength(kind) * time.delta_secs(),
+                    damage_type,
+                });
+            }
+        }
+        effects.tick(time.delta_secs());
+    }
+}
+
+/// Show one marker per kind of effect above each enemy
+pub(super) fn update_status_markers(
+    mut commands: Commands,
+    assets: Res<StatusAssets>,
+    enemies: Query<(Entity, &StatusEffects, Option<&Children>), Changed<StatusEffects>>,
+    markers: Query<&StatusMarker>,
+) {
+    for (enemy, effects, children) in enemies.iter() {
+        let kinds = effects.kinds();
+        let children = children.map(|children| children.iter().collect::<Vec<Entity>>()).unwrap_or_default();
+        let shown = children
+            .iter()
+            .filter_map(|child| markers.get(*child).ok())
+            .map(|marker| marker.0)
+            .collect::<Vec<StatusKind>>();
+        // durations change every tick, the markers only when an effect is gained or lost
+        if shown == kinds {
+            continue;
+        }
+
+        for child in children {
+            if markers.contains(child) {
+                commands.entity(child).despawn();
+            }
+        }
+        let offset = (kinds.len() as f32 - 1.0) * MARKER_SPACING / 2.0;
+        for (idx, kind) in kinds.into_iter().enumerate() {
+            let x = idx as f32 * MARKER_SPACING - offset;
+            commands.spawn((
+                StatusMarker(kind),
+                Mesh3d(assets.mesh.clone()),
+                MeshMaterial3d(assets.materials[&kind].clone()),
+                Transform::from_xyz(x, MARKER_HEIGHT, 0.0),
+                ChildOf(enemy),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn effect(kind: StatusKind, strength: f32, duration: f32, stacking: Stacking) -> StatusEffect {
+        StatusEffect {
+            kind,
+            strength,
+            duration,
+            stacking,
+        }
+    }
+
+    #[test]
+    fn stacking_rules_combine_applications() {
+        let mut effects = StatusEffects::default();
+        effects.apply(effect(StatusKind::ArmorReduction, 2.0, 3.0, Stacking::Refresh));
+        effects.apply(effect(StatusKind::ArmorReduction, 1.0, 3.0, Stacking::Refresh));
+        assert_eq!(effects.strength(StatusKind::ArmorReduction), 1.0);
+
+        let burn = StatusKind::DamageOverTime(DamageType::Fire);
+        for _ in 0..MAX_STACKS + 2 {
+            effects.apply(effect(burn, 2.0, 3.0, Stacking::Stack));
+        }
+        assert_eq!(effects.strength(burn), 2.0 * MAX_STACKS as f32);
+
+        effects.apply(effect(StatusKind::Slow, 0.5, 1.0, Stacking::MaxOf));
+        effects.apply(effect(StatusKind::Slow, 0.3, 2.0, Stacking::MaxOf));
+        assert_eq!(effects.strength(StatusKind::Slow), 0.5);
+        effects.tick(1.5);
+        assert_eq!(effects.strength(StatusKind::Slow), 0.5);
+        effects.tick(2.0);
+        assert!(effects.is_empty());
+    }
+
+    #[test]
+    fn slows_and_stuns_change_speed() {
+        let mut effects = StatusEffects::default();
+        assert_eq!(effects.speed_multiplier(), 1.0);
+        for _ in 0..3 {
+            effects.apply(effect(StatusKind::Slow, 0.3, 2.0, Stacking::Stack));
+        }
+        assert_eq!(effects.speed_multiplier(), 1.0 - MAX_SLOW);
+        effects.apply(effect(StatusKind::Stun, 1.0, 0.5, Stacking::Refresh));
+        assert_eq!(effects.speed_multiplier(), 0.0);
+        effects.tick(1.0);
+        assert_eq!(effects.speed_multiplier(), 1.0 - MAX_SLOW);
+        assert_eq!(effects.reduced_armor(3.0), 3.0);
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use super::{
+    combat::{TargetPriority, Targeting},
+    economy::{tower_cost, TowerValue, Wallet},
+    upgrade::{stats_at, TowerLevel},
+    GameScoped,
+};
+use crate::{
+    tilemap::{tile_center, GameTilemap, SetTile, TileLocation, TileType, TowerType, TILE_SCALE},
+    AppState,
+};
+
+pub const TOWER_COLOR: Color = Color::srgb(0.35, 0.4, 0.75);
+/// Colors of the two specializations of every upgrade tree
+pub const SPECIALIZATION_COLORS: [Color; 2] = [Color::srgb(0.75, 0.45, 0.2), Color::srgb(0.55, 0.25, 0.7)];
+pub const GHOST_VALID_COLOR: Color = Color::srgba(0.2, 0.9, 0.2, 0.5);
+pub const GHOST_INVALID_COLOR: Color = Color::srgba(0.9, 0.2, 0.2, 0.5);
+pub const TOWER_TYPES: [TowerType; 3] = [TowerType::T1, TowerType::T2, TowerType::T3];
+
+/// Built tower standing on tile `loc`
+#[derive(Debug, Component, Clone, PartialEq)]
+pub struct Tower {
+    pub kind: TowerType,
+    pub loc: IVec2,
+}
+
+/// Tower type chosen in the HUD, `None` when not placing towers
+#[derive(Debug, Resource, Default)]
+pub struct SelectedTower(pub Option<TowerType>);
+
+/// Built tower picked by clicking it, shown in the HUD tower panel
+#[derive(Debug, Resource, Default)]
+pub struct FocusedTower(pub Option<Entity>);
+
+/// Tile under the pointer, towers count as the tile they stand on
+#[derive(Debug, Resource, Default)]
+pub struct HoveredTile(pub Option<IVec2>);
+
+/// Request to build a tower, ignored unless the tile is Free and the tower affordable
+#[derive(Debug, Clone, Copy, Event)]
+pub struct BuildTower {
+    pub loc: IVec2,
+    pub kind: TowerType,
+}
+
+/// Preview of the selected tower over the hovered tile
+#[derive(Debug, Component)]
+pub(super) struct TowerGhost;
+
+#[derive(Debug, Resource)]
+pub struct TowerAssets {
+    meshes: HashMap<(TowerType, TowerLevel), (Handle<Mesh>, f32)>,
+    material: Handle<StandardMaterial>,
+    specializations: [Handle<StandardMaterial>; 2],
+    ghost_valid: Handle<StandardMaterial>,
+    ghost_invalid: Handle<StandardMaterial>,
+}
+
+impl TowerAssets {
+    /// Mesh of a tower along with its height, levels outside the upgrade tree get the base mesh
+    pub fn mesh(&self, kind: TowerType, level: TowerLevel) -> (Handle<Mesh>, f32) {
+        self.meshes
+            .get(&(kind, level))
+            .or_else(|| self.meshes.get(&(kind, TowerLevel::default())))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn material(&self, level: TowerLevel) -> Handle<StandardMaterial> {
+        level
+            .specialization
+            .and_then(|idx| self.specializations.get(idx))
+            .unwrap_or(&self.material)
+            .clone()
+    }
+}
+
+/// Mesh of each tower type along with its height, towers grow taller with every upgrade
+fn tower_shape(kind: TowerType, level: TowerLevel) -> (Mesh, f32) {
+    let upgrades = (level.tier - 1) as f32 + if level.specialization.is_some() { 1.0 } else { 0.0 };
+    let grow = 1.0 + 0.2 * upgrades;
+    match kind {
+        TowerType::T1 => {
+            let height = 0.8 * TILE_SCALE * grow;
+            (Cylinder::new(0.3 * TILE_SCALE, height).into(), height)
+        }
+        TowerType::T2 => {
+            let height = TILE_SCALE * grow;
+            (Cuboid::new(0.6 * TILE_SCALE, height, 0.6 * TILE_SCALE).into(), height)
+        }
+        TowerType::T3 => {
+            let height = 1.2 * TILE_SCALE * grow;
+            (
+                Cone {
+                    radius: 0.35 * TILE_SCALE,
+                    height,
+                }
+                .into(),
+                height,
+            )
+        }
+    }
+}
+
+/// Towers can only be built on Free tiles without a tower, `towers` are the tiles of the standing towers
+/// A sold tower leaves its tile set to the tower until SetTile frees it, so that tile counts as Free
+pub fn can_build(gtm: &GameTilemap, towers: &[IVec2], loc: IVec2) -> bool {
+    !towers.contains(&loc) && matches!(gtm.0.get(&loc), Some(TileType::Free | TileType::Tower(_)))
+}
+
+/// Transform placing a tower mesh of the given height on top of a tile
+pub(super) fn tower_transform(loc: IVec2, height: f32) -> Transform {
+    Transform::from_translation(tile_center(loc) + Vec3::Y * height / 2.)
+}
+
+pub(super) fn setup_tower_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let ghost = |color: Color| StandardMaterial {
+        base_color: color,
+        alpha_mode: AlphaMode::Blend,
+        ..default()
+    };
+    let assets = TowerAssets {
+        meshes: TOWER_TYPES
+            .into_iter()
+            .flat_map(|kind| TowerLevel::all().into_iter().map(move |level| (kind, level)))
+            .map(|(kind, level)| {
+                let (mesh, height) = tower_shape(kind, level);
+                ((kind, level), (meshes.add(mesh), height))
+            })
+            .collect(),
+        material: materials.add(TOWER_COLOR),
+        specializations: SPECIALIZATION_COLORS.map(|color| materials.add(color)),
+        ghost_valid: materials.add(ghost(GHOST_VALID_COLOR)),
+        ghost_invalid: materials.add(ghost(GHOST_INVALID_COLOR)),
+    };
+
+    commands.spawn((
+        TowerGhost,
+        Mesh3d(assets.mesh(TowerType::T1, TowerLevel::default()).0),
+        MeshMaterial3d(assets.ghost_valid.clone()),
+        Transform::default(),
+        Visibility::Hidden,
+        Pickable::IGNORE,
+    ));
+    commands.insert_resource(assets);
+}
+
+pub(super) fn hover_tile(
+    trigger: Trigger<Pointer<Over>>,
+    tiles: Query<&TileLocation>,
+    towers: Query<&Tower>,
+    mut hovered: ResMut<HoveredTile>,
+) {
+    let ent = trigger.target();
+    if let Ok(TileLocation(loc)) = tiles.get(ent) {
+        hovered.0 = Some(*loc);
+    } else if let Ok(tower) = towers.get(ent) {
+        hovered.0 = Some(tower.loc);
+    }
+}
+
+pub(super) fn unhover_tile(
+    trigger: Trigger<Pointer<Out>>,
+    tiles: Query<&TileLocation>,
+    towers: Query<&Tower>,
+    mut hovered: ResMut<HoveredTile>,
+) {
+    let ent = trigger.target();
+    let loc = tiles.get(ent).map(|tile| tile.0).or(towers.get(ent).map(|tower| tower.loc));
+    if loc.is_ok_and(|loc| hovered.0 == Some(loc)) {
+        hovered.0 = None;
+    }
+}
+
+/// Left click builds the selected tower on the hovered tile, or focuses a built tower when not placing
+/// Right click stops placing towers
+pub(super) fn place_tower(
+    trigger: Trigger<Pointer<Pressed>>,
+    app_state: Res<State<AppState>>,
+    tiles: Query<(), With<TileLocation>>,
+    towers: Query<(), With<Tower>>,
+    hovered: Res<HoveredTile>,
+    mut selected: ResMut<SelectedTower>,
+    mut focused: ResMut<FocusedTower>,
+    mut ev_build_tower: EventWriter<BuildTower>,
+) {
+    let ent = trigger.target();
+    // presses on the HUD are not meant for the map
+    if app_state.get() != &AppState::InGame || !(tiles.contains(ent) || towers.contains(ent)) {
+        return;
+    }
+    match trigger.event().button {
+        PointerButton::Primary => match (selected.0, hovered.0) {
+            (Some(kind), Some(loc)) => {
+                ev_build_tower.write(BuildTower { loc, kind });
+            }
+            (None, _) => focused.0 = towers.contains(ent).then_some(ent),
+            _ => (),
+        },
+        PointerButton::Secondary => selected.0 = None,
+        _ => (),
+    }
+}
+
+pub(super) fn update_ghost(
+    selected: Res<SelectedTower>,
+    hovered: Res<HoveredTile>,
+    gtm: Res<GameTilemap>,
+    wallet: Res<Wallet>,
+    assets: Res<TowerAssets>,
+    towers: Query<&Tower>,
+    mut ghost: Query<
+        (&mut Mesh3d, &mut MeshMaterial3d<StandardMaterial>, &mut Transform, &mut Visibility),
+        With<TowerGhost>,
+    >,
+) {
+    let Ok((mut mesh, mut material, mut transform, mut visibility)) = ghost.single_mut() else {
+        return;
+    };
+    let (Some(kind), Some(loc)) = (selected.0, hovered.0) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+
+    let (ghost_mesh, height) = assets.mesh(kind, TowerLevel::default());
+    mesh.0 = ghost_mesh;
+    let built = towers.iter().map(|tower| tower.loc).collect::<Vec<IVec2>>();
+    material.0 = if can_build(&gtm, &built, loc) && wallet.can_afford(tower_cost(kind)) {
+        assets.ghost_valid.clone()
+    } else {
+        assets.ghost_invalid.clone()
+    };
+    *transform = tower_transform(loc, height);
+    *visibility = Visibility::Visible;
+}
+
+pub(super) fn hide_ghost(mut ghost: Query<&mut Visibility, With<TowerGhost>>) {
+    for mut visibility in ghost.iter_mut() {
+        *visibility = Visibility::Hidden;
+    }
+}
+
+pub(super) fn build_towers(
+    mut commands: Commands,
+    mut ev_build_tower: EventReader<BuildTower>,
+    mut ev_set_tile: EventWriter<SetTile>,
+    gtm: Res<GameTilemap>,
+    assets: Res<TowerAssets>,
+    mut wallet: ResMut<Wallet>,
+    towers: Query<&Tower>,
+) {
+    // SetTile is only applied after the fixed steps of a frame, the towers tell which tiles are taken until then
+    let mut built = towers.iter().map(|tower| tower.loc).collect::<Vec<IVec2>>();
+    for BuildTower { loc, kind } in ev_build_tower.read() {
+        if !can_build(&gtm, &built, *loc) {
+            info!("Unable to build {kind:?} at {loc}, towers can only be built on free tiles");
+            continue;
+        }
+        let cost = tower_cost(*kind);
+        if !wallet.spend(cost) {
+            info!("Unable to build {kind:?} at {loc}, it costs {cost} gold and only {} is left", wallet.gold());
+            continue;
+        }
+        built.push(*loc);
+        spawn_tower(&mut commands, &assets, *kind, *loc, TowerLevel::default(), TargetPriority::default(), cost);
+        ev_set_tile.write(SetTile {
+            loc: *loc,
+            tile: TileType::Tower(*kind),
+        });
+    }
+}
+
+/// Spawn a built tower, the tile it stands on is changed separately
+pub(super) fn spawn_tower(
+    commands: &mut Commands,
+    assets: &TowerAssets,
+    kind: TowerType,
+    loc: IVec2,
+    level: TowerLevel,
+    priority: TargetPriority,
+    value: u32,
+) -> Entity {
+    let (mesh, height) = assets.mesh(kind, level);
+    commands
+        .spawn((
+            Tower { kind, loc },
+            level,
+            stats_at(kind, level),
+            Targeting {
+                priority,
+                ..default()
+            },
+            TowerValue(value),
+            Mesh3d(mesh),
+            MeshMaterial3d(assets.material(level)),
+            tower_transform(loc, height),
+            GameScoped,
+        ))
+        .id()
+}
+
+/// Turn the tiles of towers from a previous game back into Free tiles
+pub(super) fn clear_tower_tiles(
+    gtm: Res<GameTilemap>,
+    mut selected: ResMut<SelectedTower>,
+    mut focused: ResMut<FocusedTower>,
+    mut ev_set_tile: EventWriter<SetTile>,
+) {
+    selected.0 = None;
+    focused.0 = None;
+    let mut tower_tiles = gtm
+        .0
+        .iter()
+        .filter(|(_loc, tt)| matches!(tt, TileType::Tower(_)))
+        .map(|(loc, _tt)| *loc)
+        .collect::<Vec<IVec2>>();
+    tower_tiles.sort_by_key(|loc| (loc.x, loc.y));
+    ev_set_tile.write_batch(tower_tiles.into_iter().map(|loc| SetTile {
+        loc,
+        tile: TileType::Free,
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::EnemyTile;
+
+    #[test]
+    fn towers_only_build_on_free_tiles() {
+        let mut gtm = GameTilemap::new(IVec2::new(3, 1));
+        gtm.0.insert(IVec2::new(1, 0), TileType::EnemyMap(EnemyTile::Vertical));
+        gtm.0.insert(IVec2::new(2, 0), TileType::Blocked);
+
+        assert!(can_build(&gtm, &[], IVec2::new(0, 0)));
+        assert!(!can_build(&gtm, &[], IVec2::new(1, 0)));
+        assert!(!can_build(&gtm, &[], IVec2::new(2, 0)));
+        assert!(!can_build(&gtm, &[], IVec2::new(5, 0)));
+        // built on the same step, the tile is only changed afterwards
+        assert!(!can_build(&gtm, &[IVec2::new(0, 0)], IVec2::new(0, 0)));
+
+        gtm.set_tile(IVec2::new(0, 0), TileType::Tower(TowerType::T1));
+        assert!(!can_build(&gtm, &[IVec2::new(0, 0)], IVec2::new(0, 0)));
+        // sold, the tile is freed afterwards
+        assert!(can_build(&gtm, &[], IVec2::new(0, 0)));
+    }
+}
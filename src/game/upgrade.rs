@@ -0,0 +1,300 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    combat::TowerStats,
+    damage::DamageType,
+    economy::{TowerValue, Wallet},
+    status::{StatusEffect, StatusKind, Stacking},
+    tower::{tower_transform, Tower, TowerAssets},
+};
+use crate::tilemap::TowerType;
+
+/// Highest linear tier, a tower on it can pick one of the specializations of its tree
+pub const MAX_TIER: u8 = 3;
+
+/// Changes made by one upgrade, applied on top of the stats of the previous level
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UpgradeStep {
+    pub name: &'static str,
+    pub cost: u32,
+    /// Multiplier of the damage
+    pub damage: f32,
+    /// Multiplier of the fire rate
+    pub fire_rate: f32,
+    /// Tiles added to the range
+    pub range: f32,
+    /// Tiles added to the splash radius of towers firing splash projectiles
+    pub splash_radius: f32,
+    /// Effect replacing the one applied by the tower
+    pub effect: Option<StatusEffect>,
+    /// Damage type replacing the one dealt by the tower
+    pub damage_type: Option<DamageType>,
+}
+
+/// Upgrade that only costs gold, the fields to improve are filled in with struct update syntax
+const fn step(name: &'static str, cost: u32) -> UpgradeStep {
+    UpgradeStep {
+        name,
+        cost,
+        damage: 1.0,
+        fire_rate: 1.0,
+        range: 0.0,
+        splash_radius: 0.0,
+        effect: None,
+        damage_type: None,
+    }
+}
+
+/// Linear tiers followed by a choice between two specializations
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UpgradeTree {
+    /// Upgrades to tier 2 and 3 in order
+    pub tiers: [UpgradeStep; (MAX_TIER - 1) as usize],
+    pub specializations: [UpgradeStep; 2],
+}
+
+pub fn upgrade_tree(kind: TowerType) -> UpgradeTree {
+    match kind {
+        TowerType::T1 => UpgradeTree {
+            tiers: [
+                UpgradeStep { damage: 1.5, ..step("Tier 2", 40) },
+                UpgradeStep { damage: 1.3, range: 0.5, ..step("Tier 3", 70) },
+            ],
+            specializations: [
+                UpgradeStep { fire_rate: 2.0, ..step("Rapid Fire", 120) },
+                UpgradeStep {
+                    damage: 2.5,
+                    range: 2.0,
+                    fire_rate: 0.6,
+                    effect: Some(StatusEffect {
+                        kind: StatusKind::ArmorReduction,
+                        strength: 2.0,
+                        duration: 4.0,
+                        stacking: Stacking::Refresh,
+                    }),
+                    ..step("Sniper", 130)
+                },
+            ],
+        },
+        TowerType::T2 => UpgradeTree {
+            tiers: [
+                UpgradeStep { damage: 1.4, ..step("Tier 2", 90) },
+                UpgradeStep { damage: 1.2, splash_radius: 0.25, ..step("Tier 3", 140) },
+            ],
+            specializations: [
+                UpgradeStep { damage: 1.5, splash_radius: 0.75, ..step("Mortar", 220) },
+                UpgradeStep {
+                    range: 2.0,
+                    fire_rate: 1.5,
+                    effect: Some(StatusEffect {
+                        kind: StatusKind::Stun,
+                        strength: 1.0,
+                        duration: 0.5,
+                        stacking: Stacking::Refresh,
+                    }),
+                    ..step("Artillery", 200)
+                },
+            ],
+        },
+        TowerType::T3 => UpgradeTree {
+            tiers: [
+                UpgradeStep { fire_rate: 1.3, ..step("Tier 2", 60) },
+                UpgradeStep { damage: 1.5, ..step("Tier 3", 100) },
+            ],
+            specializations: [
+                UpgradeStep {
+                    fire_rate: 1.8,
+                    damage_type: Some(DamageType::Frost),
+                    effect: Some(StatusEffect {
+                        kind: StatusKind::Slow,
+                        strength: 0.5,
+                        duration: 2.0,
+                        stacking: Stacking::MaxOf,
+                    }),
+                    ..step("Storm", 170)
+                },
+                UpgradeStep { damage: 2.0, range: 1.0, ..step("Lancer", 160) },
+            ],
+        },
+    }
+}
+
+/// Position of a tower in its upgrade tree, `specialization` can only be picked on `MAX_TIER`
+#[derive(Debug, Component, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TowerLevel {
+    pub tier: u8,
+    pub specialization: Option<usize>,
+}
+
+impl Default for TowerLevel {
+    fn default() -> Self {
+        TowerLevel {
+            tier: 1,
+            specialization: None,
+        }
+    }
+}
+
+impl TowerLevel {
+    /// Every level of an upgrade tree, from the base tower to the specializations
+    pub fn all() -> Vec<TowerLevel> {
+        let tiers = (1..=MAX_TIER).map(|tier| TowerLevel {
+            tier,
+            specialization: None,
+        });
+        let specializations = (0..2).map(|specialization| TowerLevel {
+            tier: MAX_TIER,
+            specialization: Some(specialization),
+        });
+        tiers.chain(specializations).collect()
+    }
+
+    /// Whether the level is part of the upgrade trees, levels read from files may not be
+    pub fn is_valid(&self) -> bool {
+        TowerLevel::all().contains(self)
+    }
+
+    /// Upgrades bought to reach this level, in order
+    pub fn steps(&self, tree: &UpgradeTree) -> Vec<UpgradeStep> {
+        let tiers = tree.tiers.iter().take(self.tier.saturating_sub(1) as usize);
+        let specialization = self.specialization.and_then(|idx| tree.specializations.get(idx));
+        tiers.chain(specialization).copied().collect()
+    }
+
+    /// Levels this level can be upgraded to along with the upgrade bought
+    pub fn next(&self, tree: &UpgradeTree) -> Vec<(TowerLevel, UpgradeStep)> {
+        if self.tier < MAX_TIER {
+            let next = TowerLevel {
+                tier: self.tier + 1,
+                specialization: None,
+            };
+            vec![(next, tree.tiers[self.tier as usize - 1])]
+        } else if self.specialization.is_none() {
+            tree.specializations
+                .iter()
+                .enumerate()
+                .map(|(idx, step)| {
+                    let next = TowerLevel {
+                        specialization: Some(idx),
+                        ..*self
+                    };
+                    (next, *step)
+                })
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Name shown in the HUD
+    pub fn name(&self, tree: &UpgradeTree) -> String {
+        match self.specialization.and_then(|idx| tree.specializations.get(idx)) {
+            Some(step) => step.name.to_string(),
+            None => format!("Tier {}", self.tier),
+        }
+    }
+}
+
+/// Stats of a tower of the given kind and level
+pub fn stats_at(kind: TowerType, level: TowerLevel) -> TowerStats {
+    let mut stats = TowerStats::of(kind);
+    for step in level.steps(&upgrade_tree(kind)) {
+        stats.damage *= step.damage;
+        stats.fire_rate *= step.fire_rate;
+        stats.range += step.range;
+        if let Some(radius) = stats.projectile.splash_radius.as_mut() {
+            *radius += step.splash_radius;
+        }
+        if step.effect.is_some() {
+            stats.effect = step.effect;
+        }
+        if let Some(damage_type) = step.damage_type {
+            stats.damage_type = damage_type;
+        }
+    }
+    stats
+}
+
+/// Request to upgrade a tower, `choice` indexes the upgrades listed by `TowerLevel::next`
+#[derive(Debug, Clone, Copy, Event)]
+pub struct UpgradeTower {
+    pub tower: Entity,
+    pub choice: usize,
+}
+
+pub(super) fn upgrade_towers(
+    mut ev_upgrade_tower: EventReader<UpgradeTower>,
+    mut wallet: ResMut<Wallet>,
+    assets: Res<TowerAssets>,
+    mut towers: Query<(&Tower, &mut TowerLevel, &mut TowerStats, &mut TowerValue)>,
+    mut visuals: Query<(&mut Mesh3d, &mut MeshMaterial3d<StandardMaterial>, &mut Transform), With<Tower>>,
+) {
+    for UpgradeTower { tower: ent, choice } in ev_upgrade_tower.read() {
+        let Ok((tower, mut level, mut stats, mut value)) = towers.get_mut(*ent) else {
+            continue;
+        };
+        let Some((next, step)) = level.next(&upgrade_tree(tower.kind)).get(*choice).copied() else {
+            info!("{:?} at {} has no upgrade {choice}", tower.kind, tower.loc);
+            continue;
+        };
+        if !wallet.spend(step.cost) {
+            info!("Unable to upgrade to {}, it costs {} gold and only {} is left", step.name, step.cost, wallet.gold());
+            continue;
+        }
+
+        info!("Upgraded {:?} at {} to {}", tower.kind, tower.loc, step.name);
+        *level = next;
+        *stats = stats_at(tower.kind, next);
+        value.0 += step.cost;
+        if let Ok((mut mesh, mut material, mut transform)) = visuals.get_mut(*ent) {
+            let (tower_mesh, height) = assets.mesh(tower.kind, next);
+            mesh.0 = tower_mesh;
+            material.0 = assets.material(next);
+            *transform = tower_transform(tower.loc, height);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiers_come_before_specializations() {
+        let tree = upgrade_tree(TowerType::T1);
+        let base = TowerLevel::default();
+        let tier2 = base.next(&tree);
+        assert_eq!(tier2.len(), 1);
+        assert_eq!(tier2[0].0.tier, 2);
+
+        let tier3 = tier2[0].0.next(&tree)[0].0;
+        let specializations = tier3.next(&tree);
+        assert_eq!(specializations.len(), 2);
+        assert!(specializations[1].0.next(&tree).is_empty());
+        assert_eq!(specializations[1].0.steps(&tree).len(), 3);
+        assert_eq!(TowerLevel::all().len(), 5);
+        assert!(tier3.is_valid());
+        assert!(!TowerLevel { tier: 0, specialization: None }.is_valid());
+        assert!(!TowerLevel { tier: 2, specialization: Some(0) }.is_valid());
+        assert!(!TowerLevel { tier: MAX_TIER, specialization: Some(2) }.is_valid());
+    }
+
+    #[test]
+    fn upgrades_stack_on_base_stats() {
+        let base = TowerStats::of(TowerType::T2);
+        let level = TowerLevel {
+            tier: MAX_TIER,
+            specialization: Some(0),
+        };
+        let stats = stats_at(TowerType::T2, level);
+        assert!((stats.damage - base.damage * 1.4 * 1.2 * 1.5).abs() < 1e-4);
+        assert_eq!(stats.projectile.splash_radius, Some(2.0));
+        assert_eq!(stats_at(TowerType::T2, TowerLevel::default()), base);
+
+        // Storm swaps the magic bolts for frost
+        let storm = stats_at(TowerType::T3, level);
+        assert_eq!(storm.damage_type, DamageType::Frost);
+        assert_eq!(storm.effect.map(|effect| effect.strength), Some(0.5));
+    }
+}
@@ -0,0 +1,432 @@
+use std::{fmt, fs, io, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    economy::{Wallet, STARTING_GOLD},
+    enemy::{EnemyKind, FlightRoute, SpawnEnemy, WaveMember},
+    outcome::{Lives, STARTING_LIVES},
+    replay::ActiveReplay,
+};
+use crate::tilemap::EnemyPath;
+
+/// Enemies of one kind sent one after another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpawnGroup {
+    pub kind: EnemyKind,
+    pub count: u32,
+    /// Seconds between two enemies of the group
+    #[serde(default)]
+    pub interval: f32,
+    /// Seconds to wait before the first enemy of the group, counted from the end of the previous group
+    #[serde(default)]
+    pub delay: f32,
+}
+
+/// Groups of enemies sent together as one wave, in order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WaveDefinition {
+    pub groups: Vec<SpawnGroup>,
+}
+
+impl WaveDefinition {
+    /// Waves used when a map has no wave file
+    pub fn default_waves() -> Vec<WaveDefinition> {
+        let group = |kind, count, interval, delay| SpawnGroup {
+            kind,
+            count,
+            interval,
+            delay,
+        };
+        vec![
+            WaveDefinition {
+                groups: vec![group(EnemyKind::Basic, 5, 1.0, 0.0)],
+            },
+            WaveDefinition {
+                groups: vec![group(EnemyKind::Basic, 6, 0.8, 0.0), group(EnemyKind::Fast, 4, 0.6, 2.0)],
+            },
+            WaveDefinition {
+                groups: vec![
+                    group(EnemyKind::Fast, 6, 0.5, 0.0),
+                    group(EnemyKind::Tough, 3, 1.5, 2.0),
+                    group(EnemyKind::Basic, 8, 0.5, 1.0),
+                ],
+            },
+            WaveDefinition {
+                groups: vec![
+                    group(EnemyKind::Armored, 4, 1.2, 0.0),
+                    group(EnemyKind::Healer, 2, 2.0, 0.5),
+                    group(EnemyKind::Splitter, 4, 1.0, 2.0),
+                ],
+            },
+            WaveDefinition {
+                groups: vec![group(EnemyKind::Flyer, 6, 0.8, 0.0), group(EnemyKind::Basic, 8, 0.5, 1.0)],
+            },
+            WaveDefinition {
+                groups: vec![group(EnemyKind::Regenerator, 6, 0.8, 0.0), group(EnemyKind::Boss, 1, 0.0, 3.0)],
+            },
+        ]
+    }
+}
+
+/// Gameplay settings of a map, settings left out of the file use their defaults
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WaveFile {
+    #[serde(default = "default_starting_gold")]
+    pub starting_gold: u32,
+    #[serde(default = "default_lives")]
+    pub lives: u32,
+    /// Tiles flying enemies pass over on their way from the Start to the Finish tile, in order
+    #[serde(default)]
+    pub flight_waypoints: Vec<IVec2>,
+    pub waves: Vec<WaveDefinition>,
+}
+
+impl Default for WaveFile {
+    /// Settings used when a map has no wave file
+    fn default() -> Self {
+        WaveFile {
+            starting_gold: STARTING_GOLD,
+            lives: STARTING_LIVES,
+            flight_waypoints: vec![],
+            waves: WaveDefinition::default_waves(),
+        }
+    }
+}
+
+fn default_starting_gold() -> u32 {
+    STARTING_GOLD
+}
+
+fn default_lives() -> u32 {
+    STARTING_LIVES
+}
+
+#[derive(Debug)]
+pub enum WaveFileError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    /// A map without waves could never be won
+    NoWaves,
+}
+
+impl fmt::Display for WaveFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaveFileError::Io(e) => write!(f, "unable to read wave file: {e}"),
+            WaveFileError::Parse(e) => write!(f, "unable to parse wave file: {e}"),
+            WaveFileError::NoWaves => write!(f, "wave file has no waves"),
+        }
+    }
+}
+
+impl std::error::Error for WaveFileError {}
+
+/// Wave file stored next to a map, `maps/simple1.txt` uses `maps/simple1.waves.txt`
+pub fn wave_file_path(map_path: &Path) -> std::path::PathBuf {
+    map_path.with_extension("waves.txt")
+}
+
+pub fn read_wave_file(path: &Path) -> Result<WaveFile, WaveFileError> {
+    let contents = fs::read_to_string(path).map_err(WaveFileError::Io)?;
+    parse_wave_file(&contents)
+}
+
+pub fn parse_wave_file(contents: &str) -> Result<WaveFile, WaveFileError> {
+    let wave_file: WaveFile = serde_json::from_str(contents).map_err(WaveFileError::Parse)?;
+    if wave_file.waves.is_empty() {
+        return Err(WaveFileError::NoWaves);
+    }
+    Ok(wave_file)
+}
+
+/// Wave being spawned, `timer` counts down to the next enemy
+#[derive(Debug, Clone, PartialEq)]
+struct ActiveWave {
+    wave: usize,
+    group: usize,
+    spawned: u32,
+    timer: f32,
+}
+
+/// Drives enemy spawning from the wave definitions of the current map
+#[derive(Debug, Resource, Default)]
+pub struct WaveScheduler {
+    waves: Vec<WaveDefinition>,
+    /// Number of waves sent so far, the next wave is `waves[sent]`
+    sent: usize,
+    /// Waves still spawning enemies
+    active: Vec<ActiveWave>,
+    /// Waves fully spawned whose enemies are still alive
+    awaiting_clear: Vec<usize>,
+    cleared: usize,
+}
+
+impl WaveScheduler {
+    pub fn new(waves: Vec<WaveDefinition>) -> Self {
+        WaveScheduler {
+            waves,
+            ..default()
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.waves.len()
+    }
+
+    pub fn sent(&self) -> usize {
+        self.sent
+    }
+
+    pub fn has_next_wave(&self) -> bool {
+        self.sent < self.waves.len()
+    }
+
+    pub fn all_cleared(&self) -> bool {
+        self.cleared == self.waves.len()
+    }
+
+    /// No wave is spawning or waiting for its enemies to die
+    pub fn is_idle(&self) -> bool {
+        self.active.is_empty() && self.awaiting_clear.is_empty()
+    }
+
+    /// Count the first `count` waves as sent and cleared, used when restoring a game save
+    pub fn skip_waves(&mut self, count: usize) {
+        self.sent = count.min(self.waves.len());
+        self.cleared = self.sent;
+    }
+
+    /// Start spawning the next wave, returning its index
+    pub fn send_next_wave(&mut self) -> Option<usize> {
+        if !self.has_next_wave() {
+            return None;
+        }
+        let wave = self.sent;
+        self.sent += 1;
+        let timer = self.waves[wave].groups.first().map_or(0.0, |group| group.delay);
+        self.active.push(ActiveWave {
+            wave,
+            group: 0,
+            spawned: 0,
+            timer,
+        });
+        Some(wave)
+    }
+
+    /// Advance the spawn timers, returning the enemies due to spawn along with their wave
+    pub fn tick(&mut self, delta: f32) -> Vec<(usize, EnemyKind)> {
+        let mut spawns = vec![];
+        for active in self.active.iter_mut() {
+            let groups = &self.waves[active.wave].groups;
+            active.timer -= delta;
+            while active.timer <= 0.0 {
+                let Some(group) = groups.get(active.group) else {
+                    break;
+                };
+                if active.spawned < group.count {
+                    spawns.push((active.wave, group.kind));
+                    active.spawned += 1;
+                }
+                if active.spawned < group.count {
+                    active.timer += group.interval;
+                } else {
+                    active.group += 1;
+                    active.spawned = 0;
+                    active.timer += groups.get(active.group).map_or(0.0, |next| next.delay);
+                }
+            }
+        }
+
+        let (spawning, done): (Vec<ActiveWave>, Vec<ActiveWave>) = self
+            .active
+            .drain(..)
+            .partition(|active| active.group < self.waves[active.wave].groups.len());
+        self.active = spawning;
+        self.awaiting_clear.extend(done.into_iter().map(|active| active.wave));
+        spawns
+    }
+
+    /// Mark fully spawned waves without living enemies as cleared, returning them in order
+    pub fn collect_cleared(&mut self, alive: impl Fn(usize) -> bool) -> Vec<usize> {
+        let (cleared, remaining): (Vec<usize>, Vec<usize>) =
+            self.awaiting_clear.drain(..).partition(|wave| !alive(*wave));
+        self.awaiting_clear = remaining;
+        self.cleared += cleared.len();
+        cleared
+    }
+}
+
+/// Request to send the next wave
+#[derive(Debug, Clone, Copy, Event)]
+pub struct SendNextWave;
+
+#[derive(Debug, Clone, Copy, Event)]
+pub struct WaveStarted {
+    pub wave: usize,
+}
+
+/// Every enemy of the wave was spawned and none are left
+#[derive(Debug, Clone, Copy, Event)]
+pub struct WaveCleared {
+    pub wave: usize,
+}
+
+#[derive(Debug, Clone, Copy, Event)]
+pub struct AllWavesCleared;
+
+/// Waves and settings of a map, falling back to the defaults when the map has no readable wave file
+pub fn map_waves(map_path: Option<&Path>) -> WaveFile {
+    let Some(map_path) = map_path else {
+        return WaveFile::default();
+    };
+    let path = wave_file_path(map_path);
+    match read_wave_file(&path) {
+        Ok(wave_file) => wave_file,
+        Err(WaveFileError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+            info!("No wave file at {}, using default waves", path.display());
+            WaveFile::default()
+        }
+        Err(e) => {
+            warn!("{e}, using default waves");
+            WaveFile::default()
+        }
+    }
+}
+
+/// Load the waves, starting gold, lives and flight route the game is played with, see `start_replay`
+pub(super) fn load_waves(mut commands: Commands, active: Res<ActiveReplay>, enemy_path: Res<EnemyPath>) {
+    let wave_file = &active.replay.waves;
+    commands.insert_resource(Wallet::new(wave_file.starting_gold));
+    commands.insert_resource(Lives(wave_file.lives));
+    commands.insert_resource(FlightRoute::new(&enemy_path, &wave_file.flight_waypoints));
+    commands.insert_resource(WaveScheduler::new(wave_file.waves.clone()));
+}
+
+pub(super) fn send_next_wave(
+    mut ev_send_next_wave: EventReader<SendNextWave>,
+    mut scheduler: ResMut<WaveScheduler>,
+    mut ev_wave_started: EventWriter<WaveStarted>,
+) {
+    for _ev in ev_send_next_wave.read() {
+        match scheduler.send_next_wave() {
+            Some(wave) => {
+                info!("Wave {} started", wave + 1);
+                ev_wave_started.write(WaveStarted { wave });
+            }
+            None => info!("No waves left to send"),
+        }
+    }
+}
+
+pub(super) fn tick_waves(
+    time: Res<Time>,
+    mut scheduler: ResMut<WaveScheduler>,
+    mut ev_spawn_enemy: EventWriter<SpawnEnemy>,
+) {
+    for (wave, kind) in scheduler.tick(time.delta_secs()) {
+        ev_spawn_enemy.write(SpawnEnemy {
+            kind,
+            wave: Some(wave),
+        });
+    }
+}
+
+/// Runs after spawned enemies have been applied so a wave is not cleared before its last enemy exists
+pub(super) fn check_waves_cleared(
+    mut scheduler: ResMut<WaveScheduler>,
+    members: Query<&WaveMember>,
+    mut ev_wave_cleared: EventWriter<WaveCleared>,
+    mut ev_all_cleared: EventWriter<AllWavesCleared>,
+) {
+    let cleared = scheduler.collect_cleared(|wave| members.iter().any(|member| member.0 == wave));
+    if cleared.is_empty() {
+        return;
+    }
+    for wave in cleared {
+        info!("Wave {} cleared", wave + 1);
+        ev_wave_cleared.write(WaveCleared { wave });
+    }
+    if scheduler.all_cleared() {
+        info!("All waves cleared");
+        ev_all_cleared.write(AllWavesCleared);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler() -> WaveScheduler {
+        WaveScheduler::new(vec![WaveDefinition {
+            groups: vec![
+                SpawnGroup {
+                    kind: EnemyKind::Basic,
+                    count: 2,
+                    interval: 1.0,
+                    delay: 0.0,
+                },
+                SpawnGroup {
+                    kind: EnemyKind::Fast,
+                    count: 1,
+                    interval: 0.0,
+                    delay: 3.0,
+                },
+            ],
+        }])
+    }
+
+    #[test]
+    fn groups_spawn_on_interval_and_delay() {
+        let mut scheduler = scheduler();
+        assert!(scheduler.tick(10.0).is_empty());
+        assert_eq!(scheduler.send_next_wave(), Some(0));
+        assert_eq!(scheduler.send_next_wave(), None);
+
+        assert_eq!(scheduler.tick(0.0), vec![(0, EnemyKind::Basic)]);
+        assert!(scheduler.tick(0.5).is_empty());
+        assert_eq!(scheduler.tick(0.5), vec![(0, EnemyKind::Basic)]);
+        assert!(scheduler.tick(2.9).is_empty());
+        // nothing is cleared while the wave is still spawning
+        assert!(scheduler.collect_cleared(|_| false).is_empty());
+        assert_eq!(scheduler.tick(0.2), vec![(0, EnemyKind::Fast)]);
+
+        assert!(scheduler.collect_cleared(|_| true).is_empty());
+        assert!(!scheduler.is_idle());
+        assert_eq!(scheduler.collect_cleared(|_| false), vec![0]);
+        assert!(scheduler.all_cleared());
+        assert!(scheduler.is_idle());
+    }
+
+    #[test]
+    fn large_steps_spawn_everything_due() {
+        let mut scheduler = scheduler();
+        scheduler.send_next_wave();
+        assert_eq!(scheduler.tick(10.0).len(), 3);
+    }
+
+    #[test]
+    fn wave_files_parse() {
+        for map in ["maps/simple1.txt", "maps/spiral.txt"] {
+            let wave_file = read_wave_file(&wave_file_path(Path::new(map))).expect("wave file should parse");
+            assert!(!wave_file.waves.is_empty());
+        }
+        assert!(matches!(
+            read_wave_file(&wave_file_path(Path::new("maps/ground.txt"))),
+            Err(WaveFileError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn missing_settings_use_defaults() {
+        let wave_file = parse_wave_file(r#"{"waves": [{"groups": []}]}"#).expect("wave file should parse");
+        assert_eq!(wave_file.starting_gold, STARTING_GOLD);
+        assert_eq!(wave_file.lives, STARTING_LIVES);
+    }
+
+    #[test]
+    fn wave_files_need_waves() {
+        assert!(matches!(parse_wave_file(r#"{"waves": []}"#), Err(WaveFileError::NoWaves)));
+    }
+}
@@ -1,6 +1,6 @@
-use bevy::prelude::*;
+use bevy::{input::common_conditions::input_just_pressed, prelude::*};
 
-use crate::{cam_ctrl::CamState, editor::MiniTileState, tilemap::MapState, AppState};
+use crate::{cam_ctrl::CamState, editor::{EditorTool, MiniTileState}, game::enemy::SpawnEnemy, tilemap::MapState, AppState};
 
 
 #[derive(Debug, Clone)]
@@ -9,7 +9,11 @@ pub struct GameDebug;
 
 impl Plugin for GameDebug {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, print_state_changes);
+        app.add_systems(Update, print_state_changes)
+            .add_systems(
+                Update,
+                debug_spawn_enemy.run_if(in_state(AppState::InGame).and(input_just_pressed(KeyCode::KeyE))),
+            );
     }
 }
 
@@ -18,6 +22,7 @@ fn print_state_changes(
     cam_state: Res<State<CamState>>,
     map_state: Res<State<MapState>>,
     mt_state:  Res<State<MiniTileState>>,
+    tool_state: Res<State<EditorTool>>,
 ) {
     if app_state.is_changed() {
         info!("AppState: {:?}", app_state.get());
@@ -31,5 +36,13 @@ fn print_state_changes(
     if mt_state.is_changed() {
         info!("MiniTileState: {:?}", mt_state.get());
     }
+    if tool_state.is_changed() {
+        info!("EditorTool: {:?}", tool_state.get());
+    }
+
+}
 
-}
\ No newline at end of file
+/// Press E in game to spawn a default enemy on the Start tile, the replay records it like a player command
+fn debug_spawn_enemy(mut ev_spawn_enemy: EventWriter<SpawnEnemy>) {
+    ev_spawn_enemy.write(SpawnEnemy::default());
+}
@@ -2,7 +2,11 @@ use bevy::prelude::*;
 
 pub mod cam_ctrl;
 pub mod editor;
+pub mod game;
 pub mod game_debug;
+pub mod map_file;
+pub mod map_validation;
+pub mod sidecar;
 pub mod tilemap;
 pub mod ui;
 
@@ -16,6 +20,8 @@ pub enum AppState {
    Settings,
    ToEditor,
    ToGame,
+   Victory,
+   Defeat,
    Exit, 
 }
 
@@ -0,0 +1,307 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use serde_with::serde_as;
+
+use crate::tilemap::{trace_path, GameTilemap, TileType};
+
+/// Version written by this build, bump it and append a migration to `MIGRATIONS`
+/// whenever `TileType`, `EnemyTile`, `TowerType` or the envelope itself changes
+pub const MAP_FORMAT_VERSION: u32 = 2;
+
+/// Migration chain, entry `n` upgrades a version `n` file to version `n + 1`
+const MIGRATIONS: [fn(Value) -> Result<Value, String>; MAP_FORMAT_VERSION as usize] = [migrate_v0_to_v1, migrate_v1_to_v2];
+
+/// Locations of each tile type, ordered by tile type so loading never depends on HashMap iteration order
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SavedTileMap(#[serde_as(as = "Vec<(_, _)>")] pub BTreeMap<TileType, Vec<IVec2>>);
+
+impl SavedTileMap {
+    pub fn new() -> Self {
+        SavedTileMap(BTreeMap::new())
+    }
+}
+
+impl From<&GameTilemap> for SavedTileMap {
+    fn from(gtm: &GameTilemap) -> Self {
+        let mut tiles = SavedTileMap::new();
+        for (loc, tt) in gtm.0.iter() {
+            tiles.0.entry(*tt).or_default().push(*loc);
+        }
+        // keep the file contents stable between saves
+        for locs in tiles.0.values_mut() {
+            locs.sort_by_key(|loc| (loc.x, loc.y));
+        }
+        tiles
+    }
+}
+
+/// Versioned envelope stored in `maps/*.txt`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MapFile {
+    pub version: u32,
+    pub width: i32,
+    pub height: i32,
+    pub tiles: SavedTileMap,
+    /// Enemy route in walking order, from `EnemyTile::Start` to `EnemyTile::Finish`
+    pub path: Vec<IVec2>,
+}
+
+impl MapFile {
+    pub fn new(width: i32, height: i32, tiles: SavedTileMap, path: Vec<IVec2>) -> Self {
+        MapFile {
+            version: MAP_FORMAT_VERSION,
+            width,
+            height,
+            tiles,
+            path,
+        }
+    }
+
+    /// Map without any tiles, stand in until a game has recorded the map it is played on
+    pub fn empty() -> Self {
+        MapFile::new(0, 0, SavedTileMap::new(), vec![])
+    }
+
+    /// Format the tile data as a GameTilemap
+    pub fn to_gametilemap(&self) -> GameTilemap {
+        let mut gtm = GameTilemap::default();
+        for (tt, locs) in self.tiles.0.iter() {
+            for loc in locs {
+                gtm.0.insert(*loc, *tt);
+            }
+        }
+        gtm
+    }
+}
+
+#[derive(Debug)]
+pub enum MapFileError {
+    /// Contents are not valid JSON or do not match the expected layout
+    Parse(serde_json::Error),
+    /// JSON is valid but neither a legacy tile array nor a versioned envelope
+    UnknownFormat,
+    /// File was written by a newer build than the one running
+    UnsupportedVersion { found: u32, supported: u32 },
+    /// A step of the migration chain failed
+    Migration { from: u32, reason: String },
+}
+
+impl fmt::Display for MapFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapFileError::Parse(e) => write!(f, "unable to parse map file: {e}"),
+            MapFileError::UnknownFormat => write!(f, "unrecognised map file format"),
+            MapFileError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "map file version {found} is newer than the supported version {supported}, please update the game"
+            ),
+            MapFileError::Migration { from, reason } => write!(
+                f,
+                "unable to migrate map file from version {from} to {}: {reason}",
+                from + 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MapFileError {}
+
+impl From<serde_json::Error> for MapFileError {
+    fn from(e: serde_json::Error) -> Self {
+        MapFileError::Parse(e)
+    }
+}
+
+/// Parse a map file of any known version, migrating it up to `MAP_FORMAT_VERSION`
+pub fn parse_map(contents: &str) -> Result<MapFile, MapFileError> {
+    migrate_map(serde_json::from_str(contents)?)
+}
+
+fn migrate_map(mut value: Value) -> Result<MapFile, MapFileError> {
+    let version = detect_version(&value)?;
+
+    if version > MAP_FORMAT_VERSION {
+        return Err(MapFileError::UnsupportedVersion {
+            found: version,
+            supported: MAP_FORMAT_VERSION,
+        });
+    }
+
+    for from in version..MAP_FORMAT_VERSION {
+        value = MIGRATIONS[from as usize](value)
+            .map_err(|reason| MapFileError::Migration { from, reason })?;
+    }
+
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Serialize a map file, always written with the current format version
+pub fn serialize_map(map: &MapFile) -> Result<String, MapFileError> {
+    let mut map = map.clone();
+    map.version = MAP_FORMAT_VERSION;
+    Ok(serde_json::to_string(&map)?)
+}
+
+/// Serde adapter for a map kept inside another file, such as a replay
+/// It is stored as the same versioned envelope as a map file and migrated the same way when read
+pub mod embedded_map {
+    use serde::{de, Deserializer, Serializer};
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(map: &MapFile, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = map.clone();
+        map.version = MAP_FORMAT_VERSION;
+        map.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<MapFile, D::Error> {
+        migrate_map(Value::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+/// Legacy files are a bare `[[TileType, [IVec2..]]..]` array (version 0)
+fn detect_version(value: &Value) -> Result<u32, MapFileError> {
+    match value {
+        Value::Array(_) => Ok(0),
+        Value::Object(obj) => obj
+            .get("version")
+            .and_then(Value::as_u64)
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or(MapFileError::UnknownFormat),
+        _ => Err(MapFileError::UnknownFormat),
+    }
+}
+
+/// v0 -> v1: wrap the bare tile array in an envelope, dimensions derived from the largest coordinates
+fn migrate_v0_to_v1(value: Value) -> Result<Value, String> {
+    let entries = value.as_array().ok_or("expected a tile array")?;
+
+    let (mut width, mut height) = (0, 0);
+    for entry in entries {
+        let locs = entry
+            .get(1)
+            .and_then(Value::as_array)
+            .ok_or("expected [TileType, [locations..]] entries")?;
+        for loc in locs {
+            let x = loc.get(0).and_then(Value::as_i64).ok_or("expected [x, y] location")?;
+            let y = loc.get(1).and_then(Value::as_i64).ok_or("expected [x, y] location")?;
+            width = width.max(x + 1);
+            height = height.max(y + 1);
+        }
+    }
+
+    Ok(json!({
+        "version": 1,
+        "width": width,
+        "height": height,
+        "tiles": value,
+    }))
+}
+
+/// v1 -> v2: store the enemy route explicitly, walked from the Start tile to the Finish tile
+/// Maps without a complete route get an empty path
+fn migrate_v1_to_v2(mut value: Value) -> Result<Value, String> {
+    let entries = value
+        .get("tiles")
+        .and_then(Value::as_array)
+        .ok_or("expected a tile array")?;
+
+    let (mut start, mut finish) = (None, None);
+    let mut path_tiles = HashSet::new();
+    for entry in entries {
+        let Some(enemy_tile) = entry.get(0).and_then(|tt| tt.get("EnemyMap")) else {
+            continue;
+        };
+        let locs: Vec<IVec2> = serde_json::from_value(entry.get(1).cloned().unwrap_or_default())
+            .map_err(|e| format!("expected [x, y] locations: {e}"))?;
+
+        match enemy_tile.as_str() {
+            Some("Start") => start = start.or(locs.first().copied()),
+            Some("Finish") => finish = finish.or(locs.first().copied()),
+            _ => (),
+        }
+        path_tiles.extend(locs);
+    }
+
+    let path = match (start, finish) {
+        (Some(start), Some(finish)) => trace_path(start, finish, &path_tiles).unwrap_or_default(),
+        _ => vec![],
+    };
+
+    let obj = value.as_object_mut().ok_or("expected a map object")?;
+    obj.insert("version".into(), json!(2));
+    obj.insert("path".into(), json!(path));
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::EnemyTile;
+
+    #[test]
+    fn legacy_maps_migrate() {
+        for path in ["maps/simple1.txt", "maps/spiral.txt", "maps/ground.txt"] {
+            let contents = std::fs::read_to_string(path).expect("map file missing");
+            let map = parse_map(&contents).expect("legacy map should migrate");
+            assert_eq!(map.version, MAP_FORMAT_VERSION);
+            assert_eq!((map.width, map.height), (12, 12));
+            assert_eq!(map.to_gametilemap().0.len(), 144);
+        }
+    }
+
+    #[test]
+    fn legacy_path_walked_in_order() {
+        let contents = std::fs::read_to_string("maps/simple1.txt").unwrap();
+        let map = parse_map(&contents).unwrap();
+        assert_eq!(map.path, (2..=8).map(|y| IVec2::new(3, y)).collect::<Vec<IVec2>>());
+
+        let contents = std::fs::read_to_string("maps/spiral.txt").unwrap();
+        let map = parse_map(&contents).unwrap();
+        let gtm = map.to_gametilemap();
+        assert_eq!(map.path.len(), 64);
+        assert_eq!(map.path.first(), gtm.find_enemy_tile(EnemyTile::Start).as_ref());
+        assert_eq!(map.path.last(), gtm.find_enemy_tile(EnemyTile::Finish).as_ref());
+        assert!(map.path.windows(2).all(|w| (w[0] - w[1]).abs().element_sum() == 1));
+
+        let contents = std::fs::read_to_string("maps/ground.txt").unwrap();
+        assert!(parse_map(&contents).unwrap().path.is_empty());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut tiles = SavedTileMap::new();
+        tiles.0.insert(TileType::EnemyMap(EnemyTile::Start), vec![IVec2::new(0, 0)]);
+        tiles.0.insert(TileType::Free, vec![IVec2::new(1, 0), IVec2::new(0, 1)]);
+        let map = MapFile::new(2, 2, tiles, vec![IVec2::new(0, 0)]);
+
+        let parsed = parse_map(&serialize_map(&map).unwrap()).unwrap();
+        assert_eq!(parsed, map);
+    }
+
+    #[test]
+    fn newer_version_rejected() {
+        let contents = format!(
+            r#"{{"version":{},"width":1,"height":1,"tiles":[],"path":[]}}"#,
+            MAP_FORMAT_VERSION + 1
+        );
+        assert!(matches!(
+            parse_map(&contents),
+            Err(MapFileError::UnsupportedVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn unknown_format_rejected() {
+        assert!(matches!(parse_map(r#"{"tiles":[]}"#), Err(MapFileError::UnknownFormat)));
+        assert!(matches!(parse_map("not json"), Err(MapFileError::Parse(_))));
+    }
+}
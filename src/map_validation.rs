@@ -0,0 +1,258 @@
+use std::{collections::HashSet, fmt};
+
+use bevy::prelude::*;
+
+use crate::tilemap::{EnemyTile, GameTilemap, MapSize, TileType, NEIGHBOURS};
+
+/// A single problem found while validating a map, coordinates are tile locations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapProblem {
+    MissingStart,
+    MissingFinish,
+    DuplicateStart(Vec<IVec2>),
+    DuplicateFinish(Vec<IVec2>),
+    /// Path tiles which can not be reached from the Start tile
+    DisconnectedSegment(Vec<IVec2>),
+    /// Path tile with more neighbouring path tiles than its shape allows
+    Branch(IVec2),
+    /// Path tile with fewer neighbouring path tiles than its shape needs
+    DeadEnd(IVec2),
+    /// Path tile whose shape does not join its neighbours, `expected` is the shape that would
+    ShapeMismatch { loc: IVec2, found: EnemyTile, expected: EnemyTile },
+    OutOfBounds(IVec2),
+}
+
+impl fmt::Display for MapProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapProblem::MissingStart => write!(f, "no Start tile"),
+            MapProblem::MissingFinish => write!(f, "no Finish tile"),
+            MapProblem::DuplicateStart(locs) => write!(f, "multiple Start tiles at {locs:?}"),
+            MapProblem::DuplicateFinish(locs) => write!(f, "multiple Finish tiles at {locs:?}"),
+            MapProblem::DisconnectedSegment(locs) => {
+                write!(f, "path tiles not connected to Start at {locs:?}")
+            }
+            MapProblem::Branch(loc) => write!(f, "path branches at {loc}"),
+            MapProblem::DeadEnd(loc) => write!(f, "path dead ends at {loc}"),
+            MapProblem::ShapeMismatch { loc, found, expected } => {
+                write!(f, "{found:?} tile at {loc} should be {expected:?}")
+            }
+            MapProblem::OutOfBounds(loc) => write!(f, "tile at {loc} is outside the map"),
+        }
+    }
+}
+
+/// Every problem found in a map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapValidationError(pub Vec<MapProblem>);
+
+impl fmt::Display for MapValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "map not valid:")?;
+        for problem in &self.0 {
+            write!(f, "\n - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for MapValidationError {}
+
+/// Check the map has exactly one Start and Finish joined by a single unbranched path
+/// whose tile shapes match their neighbours, and that every tile lies within the map
+pub fn validate_map(gtm: &GameTilemap, map_size: &MapSize) -> Result<(), MapValidationError> {
+    let mut problems = vec![];
+
+    let mut out_of_bounds = gtm
+        .0
+        .keys()
+        .filter(|loc| !map_size.contains(**loc))
+        .copied()
+        .collect::<Vec<IVec2>>();
+    out_of_bounds.sort_by_key(|loc| (loc.x, loc.y));
+    problems.extend(out_of_bounds.into_iter().map(MapProblem::OutOfBounds));
+
+    let mut path_tiles = gtm
+        .0
+        .iter()
+        .filter_map(|(loc, tt)| match tt {
+            TileType::EnemyMap(et) => Some((*loc, *et)),
+            _ => None,
+        })
+        .collect::<Vec<(IVec2, EnemyTile)>>();
+    path_tiles.sort_by_key(|(loc, _et)| (loc.x, loc.y));
+    let path_locs = path_tiles.iter().map(|(loc, _et)| *loc).collect::<HashSet<IVec2>>();
+
+    let starts = locs_of(&path_tiles, EnemyTile::Start);
+    let finishes = locs_of(&path_tiles, EnemyTile::Finish);
+    match starts.len() {
+        0 => problems.push(MapProblem::MissingStart),
+        1 => (),
+        _ => problems.push(MapProblem::DuplicateStart(starts.clone())),
+    }
+    match finishes.len() {
+        0 => problems.push(MapProblem::MissingFinish),
+        1 => (),
+        _ => problems.push(MapProblem::DuplicateFinish(finishes)),
+    }
+
+    // shape of every tile against its neighbours
+    for (loc, et) in path_tiles.iter() {
+        let neighbours = NEIGHBOURS
+            .into_iter()
+            .filter(|dir| path_locs.contains(&(*loc + *dir)))
+            .collect::<Vec<IVec2>>();
+        let needed = match et {
+            EnemyTile::Start | EnemyTile::Finish => 1,
+            _ => 2,
+        };
+
+        if neighbours.len() > needed {
+            problems.push(MapProblem::Branch(*loc));
+        } else if neighbours.len() < needed {
+            problems.push(MapProblem::DeadEnd(*loc));
+        } else if let [a, b] = neighbours[..] {
+            let expected = EnemyTile::connecting(a, b).expect("orthogonal neighbours always connect");
+            if expected != *et {
+                problems.push(MapProblem::ShapeMismatch {
+                    loc: *loc,
+                    found: *et,
+                    expected,
+                });
+            }
+        }
+    }
+
+    // segments not reachable from the start, only meaningful with a single start
+    if let [start] = starts[..] {
+        let mut unreached = path_locs.clone();
+        flood(start, &mut unreached);
+        for (loc, _et) in path_tiles.iter() {
+            if unreached.contains(loc) {
+                let before = unreached.clone();
+                flood(*loc, &mut unreached);
+                let mut segment = before.difference(&unreached).copied().collect::<Vec<IVec2>>();
+                segment.sort_by_key(|loc| (loc.x, loc.y));
+                problems.push(MapProblem::DisconnectedSegment(segment));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(MapValidationError(problems))
+    }
+}
+
+fn locs_of(path_tiles: &[(IVec2, EnemyTile)], enemy_tile: EnemyTile) -> Vec<IVec2> {
+    path_tiles
+        .iter()
+        .filter(|(_loc, et)| *et == enemy_tile)
+        .map(|(loc, _et)| *loc)
+        .collect()
+}
+
+/// Remove every tile orthogonally connected to `from` out of `remaining`
+fn flood(from: IVec2, remaining: &mut HashSet<IVec2>) {
+    let mut stack = vec![from];
+    remaining.remove(&from);
+    while let Some(loc) = stack.pop() {
+        for dir in NEIGHBOURS {
+            if remaining.remove(&(loc + dir)) {
+                stack.push(loc + dir);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a 5x5 map from rows of characters
+    /// `S`/`F` start and finish, `|` `-` vertical and horizontal, `J` `L` `7` `r` corners
+    fn map_from(rows: [&str; 5]) -> GameTilemap {
+        let mut gtm = GameTilemap::new(IVec2::new(5, 5));
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let et = match c {
+                    'S' => EnemyTile::Start,
+                    'F' => EnemyTile::Finish,
+                    '|' => EnemyTile::Vertical,
+                    '-' => EnemyTile::Horizontal,
+                    'J' => EnemyTile::TopLeft,
+                    'L' => EnemyTile::TopRight,
+                    '7' => EnemyTile::BottomLeft,
+                    'r' => EnemyTile::BottomRight,
+                    _ => continue,
+                };
+                gtm.0.insert(IVec2::new(x as i32, y as i32), TileType::EnemyMap(et));
+            }
+        }
+        gtm
+    }
+
+    fn problems(gtm: &GameTilemap) -> Vec<MapProblem> {
+        validate_map(gtm, &MapSize(IVec2::new(5, 5))).err().map(|e| e.0).unwrap_or_default()
+    }
+
+    #[test]
+    fn valid_path_with_corners() {
+        let gtm = map_from([
+            "S-7..",
+            "..|..",
+            "..L-7",
+            "....|",
+            "....F",
+        ]);
+        assert_eq!(problems(&gtm), vec![]);
+    }
+
+    #[test]
+    fn missing_and_duplicate_endpoints() {
+        assert_eq!(
+            problems(&map_from([".....", ".....", ".....", ".....", "....."])),
+            vec![MapProblem::MissingStart, MapProblem::MissingFinish]
+        );
+        assert!(problems(&map_from(["S-F..", ".....", ".....", ".....", "S-F.."]))
+            .contains(&MapProblem::DuplicateStart(vec![IVec2::new(0, 0), IVec2::new(0, 4)])));
+    }
+
+    #[test]
+    fn branches_dead_ends_and_shapes() {
+        let gtm = map_from([
+            "S-7..",
+            "..|-.",
+            "..|..",
+            "..-..",
+            "..F..",
+        ]);
+        let problems = problems(&gtm);
+        assert!(problems.contains(&MapProblem::Branch(IVec2::new(2, 1))));
+        assert!(problems.contains(&MapProblem::DeadEnd(IVec2::new(3, 1))));
+        assert!(problems.contains(&MapProblem::ShapeMismatch {
+            loc: IVec2::new(2, 3),
+            found: EnemyTile::Horizontal,
+            expected: EnemyTile::Vertical,
+        }));
+    }
+
+    #[test]
+    fn disconnected_segments_and_bounds() {
+        let mut gtm = map_from([
+            "S-F..",
+            ".....",
+            "..-..",
+            "..-..",
+            ".....",
+        ]);
+        gtm.0.insert(IVec2::new(7, 1), TileType::Free);
+        let problems = problems(&gtm);
+        assert!(problems.contains(&MapProblem::OutOfBounds(IVec2::new(7, 1))));
+        assert!(problems.contains(&MapProblem::DisconnectedSegment(vec![
+            IVec2::new(2, 2),
+            IVec2::new(2, 3)
+        ])));
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Stem of the files kept for a map that was never saved to a file
+const UNTITLED_STEM: &str = "maps/untitled";
+
+/// Reason a file kept next to a map, such as a game save or a replay, could not be read or written
+#[derive(Debug)]
+pub enum SidecarFileError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, serde_json::Error),
+    Serialize(PathBuf, serde_json::Error),
+}
+
+impl fmt::Display for SidecarFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SidecarFileError::Io(path, e) => write!(f, "unable to access {}: {e}", path.display()),
+            SidecarFileError::Parse(path, e) => write!(f, "unable to parse {}: {e}", path.display()),
+            SidecarFileError::Serialize(path, e) => write!(f, "unable to serialize {}: {e}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for SidecarFileError {}
+
+/// File kept next to a map, `maps/simple1.txt` with extension `save.txt` uses `maps/simple1.save.txt`
+pub fn sidecar_file_path(map_path: Option<&Path>, extension: &str) -> PathBuf {
+    match map_path {
+        Some(map_path) => map_path.with_extension(extension),
+        None => PathBuf::from(format!("{UNTITLED_STEM}.{extension}")),
+    }
+}
+
+pub fn read_sidecar_file<T: DeserializeOwned>(path: &Path) -> Result<T, SidecarFileError> {
+    let contents = fs::read_to_string(path).map_err(|e| SidecarFileError::Io(path.to_path_buf(), e))?;
+    serde_json::from_str(&contents).map_err(|e| SidecarFileError::Parse(path.to_path_buf(), e))
+}
+
+pub fn write_sidecar_file<T: Serialize>(path: &Path, value: &T) -> Result<(), SidecarFileError> {
+    let contents =
+        serde_json::to_string_pretty(value).map_err(|e| SidecarFileError::Serialize(path.to_path_buf(), e))?;
+    write_atomically(path, &contents).map_err(|e| SidecarFileError::Io(path.to_path_buf(), e))
+}
+
+/// Write to a temporary file next to `path` and rename it over the original,
+/// so a failed write never leaves a truncated file behind
+pub fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let mut tmp_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?
+        .to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let written = fs::File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(contents.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp_path, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    written
+}
+
+/// File in the temp directory named after the test process, so parallel test runs and checkouts do not collide
+#[cfg(test)]
+pub(crate) fn test_file_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("td_3_{}_{name}", std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecar_files_round_trip() {
+        let path = test_file_path("sidecar_files_round_trip.save.txt");
+        write_sidecar_file(&path, &vec![3, 1, 2]).expect("file should be written");
+        write_sidecar_file(&path, &vec![4, 5]).expect("file should be replaced");
+        assert_eq!(read_sidecar_file::<Vec<u32>>(&path).expect("file should be read"), vec![4, 5]);
+        assert!(!path.with_extension("txt.tmp").exists());
+        assert!(matches!(read_sidecar_file::<String>(&path), Err(SidecarFileError::Parse(..))));
+        fs::remove_file(&path).expect("file should be removed");
+        assert!(matches!(read_sidecar_file::<Vec<u32>>(&path), Err(SidecarFileError::Io(..))));
+        // JSON object keys have to be strings
+        let unwritable = std::collections::HashMap::from([((1, 2), 3)]);
+        assert!(matches!(write_sidecar_file(&path, &unwritable), Err(SidecarFileError::Serialize(..))));
+        assert!(!path.exists());
+
+        assert_eq!(
+            sidecar_file_path(Some(Path::new("maps/simple1.txt")), "replay.txt"),
+            PathBuf::from("maps/simple1.replay.txt")
+        );
+        assert_eq!(sidecar_file_path(None, "save.txt"), PathBuf::from("maps/untitled.save.txt"));
+    }
+}
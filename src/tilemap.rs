@@ -1,21 +1,49 @@
-use crate::{editor::{MiniTile, MiniTileState}, StartGameEvent};
+use crate::{
+    editor::{history::{EditHistory, MapEdit, MapSnapshot}, EditorTool, MiniTile, MiniTileState},
+    map_file::MapFile,
+    StartGameEvent,
+};
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub const BLOCKED_TILE_COLOR: Color = Color::srgb(0.88, 0.88, 0.88);
 pub const GROUND_TILE_COLOR: Color = Color::srgb(0.15, 0.75, 0.25);
 pub const ENEMY_TILE_COLOR: Color = Color::srgb(0.75, 0.35, 0.25);
 pub const HOVER_COLOR: Color = Color::srgb(0.1, 0.65, 0.2);
 pub const TILE_SCALE: f32 = 10.0;
-pub const MAP_SIZE: i32 = 12;
+/// Size used for a fresh map before any map file has been loaded
+pub const DEFAULT_MAP_SIZE: IVec2 = IVec2::new(12, 12);
+/// Tile offsets as seen from the editor camera (map y grows towards the bottom of the screen)
+pub const TOP: IVec2 = IVec2::NEG_Y;
+pub const BOTTOM: IVec2 = IVec2::Y;
+pub const LEFT: IVec2 = IVec2::NEG_X;
+pub const RIGHT: IVec2 = IVec2::X;
+/// Orthogonal neighbour offsets, in the order paths are searched
+pub const NEIGHBOURS: [IVec2; 4] = [BOTTOM, RIGHT, TOP, LEFT];
 
 
 #[derive(Debug, Clone, Event )]
 pub struct UpdateColorMap;
 
+/// Request to change a single tile
+/// Applied by `apply_set_tile` so the GameTilemap, the tile entity, its material and the EnemyPath stay in step
+#[derive(Debug, Clone, Copy, Event)]
+pub struct SetTile {
+    pub loc: IVec2,
+    pub tile: TileType,
+}
+
+/// Sent whenever a tile changes type, whether through `SetTile` or a whole map update
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Event)]
+pub struct TileChanged {
+    pub loc: IVec2,
+    pub before: TileType,
+    pub after: TileType,
+}
+
 /// enum for tile types
-#[derive(Debug, Component, Clone, Default, PartialEq, Eq, Copy, Hash, Serialize, Deserialize)]
+#[derive(Debug, Component, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Copy, Hash, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum TileType {
     EnemyMap(EnemyTile),
@@ -38,7 +66,8 @@ pub enum MapState {
     VerifyFailed,
 }
 
-#[derive(Debug, Clone, Default, Eq, PartialEq, Copy, Hash, Serialize, Deserialize)]
+/// Shape of an enemy path tile, corners are named after the two sides they connect
+#[derive(Debug, Clone, Default, Eq, PartialEq, PartialOrd, Ord, Copy, Hash, Serialize, Deserialize)]
 pub enum EnemyTile {
     Start,
     TopLeft,
@@ -51,23 +80,99 @@ pub enum EnemyTile {
     Finish,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Copy, Hash, Serialize, Deserialize)]
+impl EnemyTile {
+    /// Middle tile shape joining the neighbours in directions `a` and `b`
+    pub fn connecting(a: IVec2, b: IVec2) -> Option<Self> {
+        EnemyTile::MIDDLE_TILES
+            .into_iter()
+            .find(|et| et.connections().is_some_and(|[c, d]| (c, d) == (a, b) || (c, d) == (b, a)))
+    }
+
+    /// Directions joined by a middle tile, `None` for Start and Finish
+    pub fn connections(&self) -> Option<[IVec2; 2]> {
+        match self {
+            EnemyTile::TopLeft => Some([TOP, LEFT]),
+            EnemyTile::TopRight => Some([TOP, RIGHT]),
+            EnemyTile::BottomLeft => Some([BOTTOM, LEFT]),
+            EnemyTile::BottomRight => Some([BOTTOM, RIGHT]),
+            EnemyTile::Horizontal => Some([LEFT, RIGHT]),
+            EnemyTile::Vertical => Some([TOP, BOTTOM]),
+            EnemyTile::Start | EnemyTile::Finish => None,
+        }
+    }
+
+    const MIDDLE_TILES: [EnemyTile; 6] = [
+        EnemyTile::TopLeft,
+        EnemyTile::TopRight,
+        EnemyTile::BottomLeft,
+        EnemyTile::BottomRight,
+        EnemyTile::Horizontal,
+        EnemyTile::Vertical,
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Copy, Hash, Serialize, Deserialize)]
 pub enum TowerType {
     T1,
     T2,
     T3,
 }
 
+/// World space centre of a tile on the ground plane
+pub fn tile_center(loc: IVec2) -> Vec3 {
+    Vec3::new(loc.x as f32 * TILE_SCALE, 0.0, loc.y as f32 * TILE_SCALE)
+}
+
+/// Dimensions of the loaded map in tiles (x: width, y: height)
+/// Layout and camera framing are derived from this instead of compile-time constants
+#[derive(Debug, Resource, Clone, Copy, PartialEq, Eq)]
+pub struct MapSize(pub IVec2);
+
+impl Default for MapSize {
+    fn default() -> Self {
+        MapSize(DEFAULT_MAP_SIZE)
+    }
+}
+
+impl MapSize {
+    pub fn width(&self) -> i32 {
+        self.0.x
+    }
+
+    pub fn height(&self) -> i32 {
+        self.0.y
+    }
+
+    /// Check if a tile location lies within the map
+    pub fn contains(&self, loc: IVec2) -> bool {
+        loc.x >= 0 && loc.y >= 0 && loc.x < self.0.x && loc.y < self.0.y
+    }
+
+    /// World space centre of the map, tiles are centred on `loc * TILE_SCALE`
+    pub fn world_center(&self) -> Vec3 {
+        Vec3::new(
+            (self.0.x - 1) as f32 * TILE_SCALE / 2.,
+            0.0,
+            (self.0.y - 1) as f32 * TILE_SCALE / 2.,
+        )
+    }
+
+    /// World space width (x) and depth (z) covered by the map
+    pub fn world_extent(&self) -> Vec2 {
+        self.0.as_vec2() * TILE_SCALE
+    }
+}
+
 /// Tilemap resource
 /// Holds the tilemap data for 1 Global Tilemap
 #[derive(Debug, Resource, Clone, Default)]
 pub struct GameTilemap(pub HashMap<IVec2, TileType>);
 
 impl GameTilemap {
-    pub fn new(size: i32) -> Self {
+    pub fn new(size: IVec2) -> Self {
         let mut gtm = GameTilemap::default();
-        for i in 0..size {
-            for j in 0..size {
+        for i in 0..size.x {
+            for j in 0..size.y {
                 gtm.0.insert(IVec2::new(i, j), TileType::Free);
             }
         }
@@ -79,18 +184,104 @@ impl GameTilemap {
             *tile.1 = TileType::Free;
         }
     }
+
+    /// Change a tile within the map, `None` when the tile does not exist or already has that type
+    pub fn set_tile(&mut self, loc: IVec2, tile: TileType) -> Option<TileChanged> {
+        let current = self.0.get_mut(&loc)?;
+        if *current == tile {
+            return None;
+        }
+        let before = std::mem::replace(current, tile);
+        Some(TileChanged { loc, before, after: tile })
+    }
+
+    /// Location of the first tile matching the given EnemyTile
+    pub fn find_enemy_tile(&self, enemy_tile: EnemyTile) -> Option<IVec2> {
+        self.0
+            .iter()
+            .filter(|(_loc, tt)| **tt == TileType::EnemyMap(enemy_tile))
+            .map(|(loc, _tt)| *loc)
+            .min_by_key(|loc| (loc.x, loc.y))
+    }
+
+    /// Shape the tiles of an ordered route from their predecessor and successor,
+    /// the first tile becomes Start and the last Finish
+    pub fn autotile_path(&mut self, path: &[IVec2]) {
+        for (idx, loc) in path.iter().enumerate() {
+            let enemy_tile = if idx == 0 {
+                EnemyTile::Start
+            } else if idx == path.len() - 1 {
+                EnemyTile::Finish
+            } else {
+                // non adjacent waypoints keep whatever shape they were given
+                let Some(et) = EnemyTile::connecting(path[idx - 1] - *loc, path[idx + 1] - *loc) else {
+                    continue;
+                };
+                et
+            };
+            self.0.insert(*loc, TileType::EnemyMap(enemy_tile));
+        }
+    }
+
+    /// Walk the enemy tiles from `EnemyTile::Start` to `EnemyTile::Finish`
+    pub fn trace_enemy_path(&self) -> Option<Vec<IVec2>> {
+        let start = self.find_enemy_tile(EnemyTile::Start)?;
+        let finish = self.find_enemy_tile(EnemyTile::Finish)?;
+        let path_tiles = self
+            .0
+            .iter()
+            .filter(|(_loc, tt)| matches!(tt, TileType::EnemyMap(_)))
+            .map(|(loc, _tt)| *loc)
+            .collect::<HashSet<IVec2>>();
+        trace_path(start, finish, &path_tiles)
+    }
+}
+
+/// Depth first walk over orthogonally adjacent `path_tiles` from `start` to `finish`
+/// Dead ends are backtracked out of, so stray path tiles do not end up in the route
+pub fn trace_path(start: IVec2, finish: IVec2, path_tiles: &HashSet<IVec2>) -> Option<Vec<IVec2>> {
+    let mut route = vec![start];
+    // next NEIGHBOURS index to try for each tile in the route
+    let mut next_neighbour = vec![0];
+    let mut visited = HashSet::from([start]);
+
+    while let Some(&current) = route.last() {
+        if current == finish {
+            return Some(route);
+        }
+
+        let idx = next_neighbour.last_mut().expect("route and next_neighbour have equal length");
+        if *idx >= NEIGHBOURS.len() {
+            route.pop();
+            next_neighbour.pop();
+            continue;
+        }
+
+        let next = current + NEIGHBOURS[*idx];
+        *idx += 1;
+        if path_tiles.contains(&next) && visited.insert(next) {
+            route.push(next);
+            next_neighbour.push(0);
+        }
+    }
+    None
 }
 
 pub struct Tilemap;
 impl Plugin for Tilemap {
     fn build(&self, app: &mut App) {
         app
-            .insert_resource(GameTilemap::new(MAP_SIZE))
+            .insert_resource(GameTilemap::new(DEFAULT_MAP_SIZE))
+            .insert_resource(MapSize(DEFAULT_MAP_SIZE))
             .insert_resource(EnemyPath(None))
             .init_state::<MapState>()
             .add_event::<UpdateColorMap>()
+            .add_event::<SetTile>()
+            .add_event::<TileChanged>()
             .add_systems(Startup, setup_tilemap)
-            .add_systems(Update, (spawn_map, update_tile_colors));
+            .add_systems(Update, (spawn_map, respawn_map, apply_set_tile, update_tile_colors).chain())
+            // PostUpdate so the shapes are in place before a loaded map is verified
+            .add_systems(PostUpdate, autotile_enemy_path.run_if(resource_changed::<EnemyPath>));
     }
 }
 
@@ -106,50 +297,73 @@ fn spawn_map(
     for _ev in ev_start_game.read() {
         if map_state.as_ref() != &MapState::Spawned {
             // Spawn Ground Tiles
-            let map = gtm.0.clone();
-
-            for (v, tile) in map.iter() {
-                let tile_color: Color;
-                match tile {
-                    TileType::EnemyMap(_et) => {
-                        tile_color = ENEMY_TILE_COLOR;
-                    }
-                    TileType::Blocked => {
-                        tile_color = GROUND_TILE_COLOR;
-                    }
-                    TileType::Free => {
-                        tile_color = GROUND_TILE_COLOR;
-                    }
-                    TileType::Tower(_tt) => {
-                        tile_color = GROUND_TILE_COLOR;
-                    }
-                }
-
-                commands
-                    .spawn((
-                        Mesh3d(meshes.add(Cuboid::new(1.0 * TILE_SCALE, 0.1, 1.0 * TILE_SCALE))),
-                        MeshMaterial3d(materials.add(tile_color)),
-                        Transform::from_xyz(v.x as f32 * TILE_SCALE, 0.0, v.y as f32 * TILE_SCALE),
-                        TileLocation(IVec2::new(v.x, v.y)),
-                        tile.clone(),
-                    ))
-                    .observe(alter_tile::<Pointer<Pressed>>())
-                    .observe(recolor::<Pointer<Over>>(0.15))
-                    .observe(recolor::<Pointer<Out>>(0.0));
-
-                // Spawn Ambient Light
-                commands.insert_resource(AmbientLight {
-                    color: Color::WHITE,
-                    brightness: 1000.0,
-                    ..default()
-                });
-
-                next_map_state.set(MapState::Spawned);
-            }
+            spawn_tiles(&mut commands, &gtm, &mut meshes, &mut materials);
+
+            // Spawn Ambient Light
+            commands.insert_resource(AmbientLight {
+                color: Color::WHITE,
+                brightness: 1000.0,
+                ..default()
+            });
+
+            next_map_state.set(MapState::Spawned);
         }
     }
 }
 
+/// Despawn and respawn the tile entities when a map with different dimensions is loaded
+fn respawn_map(
+    mut commands: Commands,
+    map_size: Res<MapSize>,
+    gtm: Res<GameTilemap>,
+    map_state: Res<State<MapState>>,
+    tiles: Query<Entity, With<TileLocation>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !map_size.is_changed() || map_size.is_added() || map_state.get() == &MapState::NotSpawned {
+        return;
+    }
+
+    for ent in tiles.iter() {
+        commands.entity(ent).despawn();
+    }
+    spawn_tiles(&mut commands, &gtm, &mut meshes, &mut materials);
+}
+
+fn spawn_tiles(
+    commands: &mut Commands,
+    gtm: &GameTilemap,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+) {
+    // spawn in a stable order so tile entities do not depend on HashMap iteration order
+    let mut tiles = gtm.0.iter().collect::<Vec<(&IVec2, &TileType)>>();
+    tiles.sort_by_key(|(loc, _tile)| (loc.x, loc.y));
+    for (v, tile) in tiles {
+        commands
+            .spawn((
+                Mesh3d(meshes.add(Cuboid::new(1.0 * TILE_SCALE, 0.1, 1.0 * TILE_SCALE))),
+                MeshMaterial3d(materials.add(tile_color(tile))),
+                Transform::from_translation(tile_center(*v)),
+                TileLocation(IVec2::new(v.x, v.y)),
+                tile.clone(),
+            ))
+            .observe(alter_tile::<Pointer<Pressed>>())
+            .observe(recolor::<Pointer<Over>>(0.15))
+            .observe(recolor::<Pointer<Out>>(0.0));
+    }
+}
+
+/// Base material color of a tile type
+pub fn tile_color(tile: &TileType) -> Color {
+    match tile {
+        TileType::EnemyMap(_et) => ENEMY_TILE_COLOR,
+        TileType::Blocked => BLOCKED_TILE_COLOR,
+        TileType::Free | TileType::Tower(_) => GROUND_TILE_COLOR,
+    }
+}
+
 /// Scale color up and down
 fn recolor<E>(
     scale: f32,
@@ -180,35 +394,71 @@ fn recolor<E>(
 
 fn alter_tile<E>() -> impl Fn(
     Trigger<E>,
-    Query<&mut MeshMaterial3d<StandardMaterial>>,
-    ResMut<Assets<StandardMaterial>>,
     Query<&TileType, With<MiniTile>>,
     Res<State<MiniTileState>>,
-    Query<&mut TileType, Without<MiniTile>>,
+    Res<State<EditorTool>>,
+    Query<&TileLocation>,
+    (Res<GameTilemap>, Res<EnemyPath>, Res<MapSize>),
+    ResMut<EditHistory>,
+    EventWriter<SetTile>,
 ) {
-    move |trigger, mut query, mut materials, tile_type, minitile_state, mut tt_query | {
-        if minitile_state.get() == &MiniTileState::Spawned {
+    move |trigger, tile_type, minitile_state, tool, tiles, (gtm, enemy_path, map_size), mut history, mut ev_set_tile| {
+        if minitile_state.get() == &MiniTileState::Spawned && tool.get() == &EditorTool::Tile {
             let selected_tt = tile_type.single().expect("no TileType found..");
-            let ent = trigger.target();
-            let mut mat = query.get_mut(ent).expect("No Mat found for ent");
-
-            let mut tiletype = tt_query.get_mut(ent).expect("No TileType Found for ent.. ");
-            *tiletype = selected_tt.clone();
-
-            match selected_tt {
-                TileType::EnemyMap(_enemy_tile) => mat.0 = materials.add(ENEMY_TILE_COLOR),
-                TileType::Blocked => mat.0 = materials.add(BLOCKED_TILE_COLOR),
-                TileType::Free => mat.0 = materials.add(GROUND_TILE_COLOR),
-                TileType::Tower(_tower_type) => mat.0 = materials.add(GROUND_TILE_COLOR),
+            let tile_loc = tiles.get(trigger.target()).expect("No TileLocation Found for ent.. ");
+
+            if gtm.0.get(&tile_loc.0).is_some_and(|tt| tt != selected_tt) {
+                // painting a path tile may retrace and reshape the route, the edit covers those tiles too
+                let before = MapSnapshot::capture(&gtm, &enemy_path, &map_size);
+                let after = before.painted(&[tile_loc.0], *selected_tt);
+                history.record(MapEdit::diff("Tile", &before, &after));
+                ev_set_tile.write(SetTile { loc: tile_loc.0, tile: *selected_tt });
             }
-            
-            // TODO need GTM update in order to retain the tile_type after mouse leaves hover 
         }
     }
 }
 
+/// Ordered enemy route, the first tile is `EnemyTile::Start` and the last `EnemyTile::Finish`
 #[derive(Debug, Resource, Clone)]
-pub struct EnemyPath(Option<Vec<IVec2>>);
+pub struct EnemyPath(pub Option<Vec<IVec2>>);
+
+impl EnemyPath {
+    pub fn tiles(&self) -> &[IVec2] {
+        self.0.as_deref().unwrap_or_default()
+    }
+
+    pub fn start(&self) -> Option<IVec2> {
+        self.tiles().first().copied()
+    }
+
+    pub fn finish(&self) -> Option<IVec2> {
+        self.tiles().last().copied()
+    }
+
+    /// Walk the route again after path tiles were painted directly, an incomplete path becomes empty
+    pub fn retrace(&mut self, gtm: &GameTilemap) {
+        self.0 = Some(gtm.trace_enemy_path().unwrap_or_default());
+    }
+
+    /// Whether a tile fits the route, Start and Finish on its ends, path tiles along it and none off it
+    pub fn fits(&self, loc: IVec2, tile: TileType) -> bool {
+        let ends = [self.start(), self.finish()];
+        match tile {
+            TileType::EnemyMap(EnemyTile::Start) => self.start() == Some(loc),
+            TileType::EnemyMap(EnemyTile::Finish) => self.finish() == Some(loc),
+            TileType::EnemyMap(_) => self.tiles().contains(&loc) && !ends.contains(&Some(loc)),
+            _ => !self.tiles().contains(&loc),
+        }
+    }
+
+    /// Whether path tiles were added or removed outside of the route, edits made along with the route keep it
+    pub fn needs_retrace(&self, changes: &[TileChanged]) -> bool {
+        changes.iter().any(|change| {
+            let path_tile = |tile| matches!(tile, TileType::EnemyMap(_));
+            (path_tile(change.before) || path_tile(change.after)) && !self.fits(change.loc, change.after)
+        })
+    }
+}
 
 //// System to setup the tilemap on Startup
 fn setup_tilemap(
@@ -237,16 +487,22 @@ fn setup_tilemap(
         enemy_path.0 = default_path;
     }
 
-    // First tile is Start and last Tile is finish
-    for (idx, tile) in enemy_path.0.clone().expect("no path..").iter().enumerate() {
-        if idx == 0 {
-            gtm.0.insert(*tile, TileType::EnemyMap(EnemyTile::Start));
-        } else if idx == enemy_path.0.clone().unwrap().len() - 1 {
-            gtm.0.insert(*tile, TileType::EnemyMap(EnemyTile::Finish));
-        } else {
-            gtm.0.insert(*tile, TileType::EnemyMap(EnemyTile::Vertical));
-        }
+    // First tile is Start, last Tile is finish and the rest are shaped by their neighbours
+    gtm.autotile_path(enemy_path.tiles());
+}
+
+/// Re-shape the path tiles whenever the EnemyPath is edited or loaded
+fn autotile_enemy_path(
+    mut gtm: ResMut<GameTilemap>,
+    enemy_path: Res<EnemyPath>,
+    mut ev_update_colormap: EventWriter<UpdateColorMap>,
+) {
+    // the startup path is shaped by setup_tilemap
+    if enemy_path.is_added() {
+        return;
     }
+    gtm.autotile_path(enemy_path.tiles());
+    ev_update_colormap.write(UpdateColorMap);
 }
 
 /// Callable function to update the GameTilemap and EnemyPath
@@ -257,23 +513,52 @@ fn setup_tilemap(
 /// 3. Map Verification Occurs -> MapState::Reloaded
 pub fn update_gametilemap(
     gtm: &mut ResMut<GameTilemap>,
+    map_size: &mut ResMut<MapSize>,
     enemy_path: &mut ResMut<EnemyPath>,
-    loaded_map: &GameTilemap,
+    loaded_map: &MapFile,
     map_nextstate: &mut ResMut<NextState<MapState>>, 
     ev_update_colormap: &mut EventWriter<UpdateColorMap>,
 ) {
-    gtm.0 = loaded_map.0.clone();
+    *gtm.as_mut() = loaded_map.to_gametilemap();
+    // only flag a change when the dimensions differ so the tiles are not respawned needlessly
+    map_size.set_if_neq(MapSize(IVec2::new(loaded_map.width, loaded_map.height)));
     map_nextstate.set(MapState::NeedsVerify);
-    enemy_path.0 = Some(vec![]);
+    enemy_path.0 = Some(loaded_map.path.clone());
 
-    // update EnemyPath with latest GTM
-    for (t_loc, tt) in gtm.0.iter() {
-        if let TileType::EnemyMap(_et) = tt {
-            enemy_path.0.as_mut().unwrap().push(*t_loc);
+    ev_update_colormap.write(UpdateColorMap);
+}
+
+/// Apply SetTile requests to the GameTilemap and the matching tile entities
+/// The EnemyPath is walked again whenever a path tile was added or removed outside of the route
+pub fn apply_set_tile(
+    mut ev_set_tile: EventReader<SetTile>,
+    mut ev_tile_changed: EventWriter<TileChanged>,
+    mut gtm: ResMut<GameTilemap>,
+    mut enemy_path: ResMut<EnemyPath>,
+    mut tiles: Query<(&TileLocation, &mut TileType, &mut MeshMaterial3d<StandardMaterial>)>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let changes = ev_set_tile
+        .read()
+        .filter_map(|SetTile { loc, tile }| gtm.set_tile(*loc, *tile))
+        .collect::<Vec<TileChanged>>();
+    if changes.is_empty() {
+        return;
+    }
+
+    for (tile_loc, mut tt, mut mat) in tiles.iter_mut() {
+        if changes.iter().any(|change| change.loc == tile_loc.0) {
+            *tt = gtm.0[&tile_loc.0];
+            mat.0 = materials.add(tile_color(&tt));
         }
     }
 
-    ev_update_colormap.write(UpdateColorMap);
+    // the changed EnemyPath is auto-tiled into the GameTilemap
+    if enemy_path.needs_retrace(&changes) {
+        enemy_path.retrace(&gtm);
+    }
+
+    ev_tile_changed.write_batch(changes);
 }
 
 /// System to update the colors of the tiles based on their type
@@ -283,7 +568,9 @@ pub fn update_tile_colors(
     gtm: Res<GameTilemap>,
     mut query: Query<(&TileLocation, &mut TileType, &mut MeshMaterial3d<StandardMaterial>)>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    map_state: Res<State<MapState>>,
     mut next_map_state: ResMut<NextState<MapState>>,
+    mut ev_tile_changed: EventWriter<TileChanged>,
 ) {
     if ev_update_colormap.is_empty() {
         return;
@@ -291,23 +578,105 @@ pub fn update_tile_colors(
 
     // Update the colors of the tiles based on their type
     for (tile_loc, mut tt, mut mat) in query.iter_mut() {
-        let tile = gtm.0.get(&tile_loc.0).unwrap();
-        match tile {
-            TileType::EnemyMap(_et) => {
-                mat.0 = materials.add(ENEMY_TILE_COLOR);
-            }
-            TileType::Blocked => {
-                mat.0 = materials.add(BLOCKED_TILE_COLOR);
-            }
-            TileType::Free => {
-                mat.0 = materials.add(GROUND_TILE_COLOR);
-            }
-            TileType::Tower(_tt) => {
-                mat.0 = materials.add(GROUND_TILE_COLOR);
-            }
+        // tiles outside of a newly loaded map are being respawned by `respawn_map`
+        let Some(tile) = gtm.0.get(&tile_loc.0) else {
+            continue;
+        };
+        mat.0 = materials.add(tile_color(tile));
+        if *tt != *tile {
+            ev_tile_changed.write(TileChanged {
+                loc: tile_loc.0,
+                before: *tt,
+                after: *tile,
+            });
+            *tt = *tile;
         }
-        *tt = tile.clone();
     }
 
-    next_map_state.set(MapState::Spawned);
+    // a freshly loaded map still has to go through verification
+    if map_state.get() != &MapState::NeedsVerify
+        && !matches!(*next_map_state, NextState::Pending(MapState::NeedsVerify))
+    {
+        next_map_state.set(MapState::Spawned);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_square_map_size() {
+        let map_size = MapSize(IVec2::new(20, 8));
+        assert_eq!(GameTilemap::new(map_size.0).0.len(), 160);
+        assert!(map_size.contains(IVec2::new(19, 7)));
+        assert!(!map_size.contains(IVec2::new(7, 19)));
+        assert!(!map_size.contains(IVec2::new(-1, 0)));
+        assert_eq!(map_size.world_center(), Vec3::new(9.5 * TILE_SCALE, 0.0, 3.5 * TILE_SCALE));
+        assert_eq!(map_size.world_extent(), Vec2::new(20.0 * TILE_SCALE, 8.0 * TILE_SCALE));
+    }
+
+    #[test]
+    fn trace_path_backtracks_dead_ends() {
+        // start (0,0) -> (0,1) -> (1,1) -> finish (2,1), with a dead end branch at (0,2)
+        let tiles = [(0, 0), (0, 1), (0, 2), (1, 1), (2, 1)]
+            .map(|(x, y)| IVec2::new(x, y))
+            .into_iter()
+            .collect::<HashSet<IVec2>>();
+        let route = trace_path(IVec2::new(0, 0), IVec2::new(2, 1), &tiles).unwrap();
+        assert_eq!(
+            route,
+            vec![IVec2::new(0, 0), IVec2::new(0, 1), IVec2::new(1, 1), IVec2::new(2, 1)]
+        );
+        assert_eq!(trace_path(IVec2::new(0, 0), IVec2::new(5, 5), &tiles), None);
+    }
+
+    #[test]
+    fn autotile_shapes_corners() {
+        // (0,0) -> (1,0) -> (1,1) -> (2,1) -> (2,2)
+        let path = [(0, 0), (1, 0), (1, 1), (2, 1), (2, 2)].map(|(x, y)| IVec2::new(x, y));
+        let mut gtm = GameTilemap::new(IVec2::new(3, 3));
+        gtm.autotile_path(&path);
+
+        let shape = |x, y| gtm.0[&IVec2::new(x, y)];
+        assert_eq!(shape(0, 0), TileType::EnemyMap(EnemyTile::Start));
+        assert_eq!(shape(1, 0), TileType::EnemyMap(EnemyTile::BottomLeft));
+        assert_eq!(shape(1, 1), TileType::EnemyMap(EnemyTile::TopRight));
+        assert_eq!(shape(2, 1), TileType::EnemyMap(EnemyTile::BottomLeft));
+        assert_eq!(shape(2, 2), TileType::EnemyMap(EnemyTile::Finish));
+        assert_eq!(shape(0, 2), TileType::Free);
+    }
+
+    #[test]
+    fn set_tile_reports_changes_and_path_retraces() {
+        let mut gtm = GameTilemap::new(IVec2::new(3, 3));
+        let route = vec![IVec2::new(0, 0), IVec2::new(0, 1), IVec2::new(0, 2)];
+        gtm.autotile_path(&route);
+
+        assert_eq!(gtm.set_tile(IVec2::new(2, 2), TileType::Free), None);
+        assert_eq!(gtm.set_tile(IVec2::new(5, 5), TileType::Blocked), None);
+        assert_eq!(
+            gtm.set_tile(IVec2::new(2, 2), TileType::Blocked),
+            Some(TileChanged {
+                loc: IVec2::new(2, 2),
+                before: TileType::Free,
+                after: TileType::Blocked,
+            })
+        );
+
+        let mut enemy_path = EnemyPath(None);
+        enemy_path.retrace(&gtm);
+        assert_eq!(enemy_path.tiles(), route);
+
+        // tiles off the route and reshaped route tiles keep it
+        let reshaped = gtm.set_tile(IVec2::new(0, 1), TileType::EnemyMap(EnemyTile::Horizontal)).unwrap();
+        let off_route = gtm.set_tile(IVec2::new(1, 1), TileType::Blocked).unwrap();
+        assert!(!enemy_path.needs_retrace(&[reshaped, off_route]));
+
+        // breaking the path leaves no route to walk
+        let broken = gtm.set_tile(IVec2::new(0, 1), TileType::Free).unwrap();
+        assert!(enemy_path.needs_retrace(&[broken]));
+        enemy_path.retrace(&gtm);
+        assert!(enemy_path.tiles().is_empty());
+    }
 }
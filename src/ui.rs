@@ -1,6 +1,6 @@
 use bevy::{input::common_conditions::input_just_pressed, prelude::*};
 
-use crate::{tilemap::TileType, AppState, StartGameEvent};
+use crate::{editor::EditorTool, tilemap::{TileType, TowerType}, AppState, StartGameEvent};
 
 pub const NORMAL_BUTTON: Color = Color::srgb(0.15, 0.15, 0.15);
 pub const HOVERED_BUTTON: Color = Color::srgb(0.25, 0.25, 0.25);
@@ -15,6 +15,7 @@ impl Plugin for Ui {
     fn build(&self, app: &mut App) {
         app.add_event::<StartGameEvent>()
             .add_systems(PostStartup, display_menu)
+            .add_systems(OnEnter(AppState::StartMenu), show_menu)
             .add_systems(
                 Update,
                 (menu_button_system)
@@ -27,6 +28,10 @@ impl Plugin for Ui {
                 pause_menu.run_if(
                     not(in_state(AppState::PauseMenu))
                         .and(not(in_state(AppState::StartMenu)))
+                        // the game pauses in place instead, see `speed_keys`
+                        .and(not(in_state(AppState::InGame)))
+                        .and(not(in_state(AppState::Victory)))
+                        .and(not(in_state(AppState::Defeat)))
                         .and(
                             input_just_pressed(KeyCode::KeyP)
                                 .or(input_just_pressed(KeyCode::Escape)),
@@ -40,17 +45,28 @@ impl Plugin for Ui {
     }
 }
 
+/// Show the menu over the game or editor, leaving the buttons of their HUDs as they were
 fn pause_menu(
     mut app_state: ResMut<NextState<AppState>>,
-    mut buttons: Query<&mut Visibility, With<Button>>,
-    mut nodes: Query<&mut Node, With<MenuUI>>,
+    nodes: Query<(&mut Node, &Children), With<MenuUI>>,
+    buttons: Query<&mut Visibility, With<Button>>,
 ) {
     app_state.set(AppState::PauseMenu);
-    for mut node in nodes.iter_mut() {
+    show_menu(nodes, buttons);
+}
+
+/// Show the starting menu again when returning to it from a finished game or pausing
+fn show_menu(
+    mut nodes: Query<(&mut Node, &Children), With<MenuUI>>,
+    mut buttons: Query<&mut Visibility, With<Button>>,
+) {
+    for (mut node, children) in nodes.iter_mut() {
         node.display = Display::default();
-    }
-    for mut butt in buttons.iter_mut() {
-        butt.toggle_visible_hidden();
+        for child in children.iter() {
+            if let Ok(mut vis) = buttons.get_mut(child) {
+                *vis = Visibility::Inherited;
+            }
+        }
     }
 }
 
@@ -93,9 +109,27 @@ pub enum MenuType {
     Settings,
     LevelEdit,
     Save,
+    SaveAs,
     Load,
     Exit,
     Clear,
+    Tool(EditorTool),
+    Undo,
+    Redo,
+    NextWave,
+    Tower(TowerType),
+    TargetPriority,
+    SellTower,
+    Upgrade(usize),
+    SaveGame,
+    LoadGame,
+    Retry,
+    MainMenu,
+    PauseGame,
+    /// Speed multiplier of the game
+    GameSpeed(u32),
+    SaveReplay,
+    PlayReplay,
 }
 
 #[derive(Debug, Component, Default)]